@@ -0,0 +1,316 @@
+use crate::decimal::Decimal;
+use crate::ledger::{ Account, LedgerClient };
+use crate::validation::TransactionData;
+use actix_web::http::StatusCode;
+use std::collections::HashMap;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tokio::time::{ Duration, Instant };
+
+#[derive(Debug, Error)]
+pub enum AccountError {
+    #[error("Nonce {nonce} of {address} was already used, the next nonce is {expected}")]
+    NonceReused {
+        address: String,
+        nonce: u64,
+        expected: u64,
+    },
+    #[error("Nonce {nonce} of {address} skips ahead, the next nonce is {expected}")]
+    NonceGap {
+        address: String,
+        nonce: u64,
+        expected: u64,
+    },
+    #[error("{address} holds {balance} {denom} but its pending transactions need {required}")]
+    InsufficientFunds {
+        address: String,
+        denom: String,
+        balance: Decimal,
+        required: Decimal,
+    },
+    #[error("{address} has a transaction whose fee is still being paid; its next nonce is known once that is done")]
+    FeesPending {
+        address: String,
+    },
+    #[error("Amount overflows")]
+    Overflow,
+    #[error("Failed to fetch the account from the ledger: {0}")]
+    Ledger(String),
+}
+
+impl AccountError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AccountError::NonceReused { .. } | AccountError::FeesPending { .. } => StatusCode::CONFLICT,
+            AccountError::NonceGap { .. } | AccountError::Overflow => StatusCode::BAD_REQUEST,
+            AccountError::InsufficientFunds { .. } => StatusCode::PAYMENT_REQUIRED,
+            AccountError::Ledger(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    /// Machine-readable body of the error response
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut body =
+            serde_json::json!({
+            "error": match self {
+                AccountError::NonceReused { .. } => "nonce_reused",
+                AccountError::NonceGap { .. } => "nonce_gap",
+                AccountError::InsufficientFunds { .. } => "insufficient_funds",
+                AccountError::FeesPending { .. } => "fees_pending",
+                AccountError::Overflow => "invalid_amount",
+                AccountError::Ledger(_) => "ledger_unavailable",
+            },
+            "message": self.to_string(),
+        });
+        match self {
+            AccountError::NonceReused { nonce, expected, .. } | AccountError::NonceGap { nonce, expected, .. } => {
+                body["nonce"] = (*nonce).into();
+                body["expected_nonce"] = (*expected).into();
+            }
+            AccountError::InsufficientFunds { denom, balance, required, .. } => {
+                body["denom"] = denom.clone().into();
+                body["balance"] = serde_json::to_value(balance).unwrap_or_default();
+                body["required"] = serde_json::to_value(required).unwrap_or_default();
+            }
+            AccountError::FeesPending { .. } | AccountError::Overflow | AccountError::Ledger(_) => {}
+        }
+        body
+    }
+}
+
+/// Amount a transaction takes from its sender's balance: the amount sent plus the fee, which its
+/// fee payments take
+fn cost(transaction: &TransactionData) -> Result<Decimal, AccountError> {
+    transaction.amount.checked_add(&transaction.fee).map_err(|_| AccountError::Overflow)
+}
+
+/// Check a transaction against its sender's account: its nonce must be the ledger's next one
+/// and the balance must cover its amount and fee.
+///
+/// Every transaction pays a fee, in one payment per validator share with the nonces following
+/// its own. How many depends on the validators that commit it, so a sender's next nonce is only
+/// known once those payments are made, and no transaction is accepted while another one of the
+/// same sender is pending.
+pub fn check_transaction(
+    account: &Account,
+    pending: &[TransactionData],
+    transaction: &TransactionData
+) -> Result<(), AccountError> {
+    // Pending transactions with a nonce below the ledger's were already applied
+    if pending.iter().any(|pending| pending.sender == transaction.sender && pending.nonce >= account.nonce) {
+        return Err(AccountError::FeesPending { address: transaction.sender.to_string() });
+    }
+
+    let expected = account.nonce;
+    if transaction.nonce < expected {
+        return Err(AccountError::NonceReused {
+            address: transaction.sender.to_string(),
+            nonce: transaction.nonce,
+            expected,
+        });
+    }
+    if transaction.nonce > expected {
+        return Err(AccountError::NonceGap {
+            address: transaction.sender.to_string(),
+            nonce: transaction.nonce,
+            expected,
+        });
+    }
+
+    let required = cost(transaction)?;
+    let balance = account.balances.get(&transaction.denom).copied().unwrap_or_default();
+    if required > balance {
+        return Err(AccountError::InsufficientFunds {
+            address: transaction.sender.to_string(),
+            denom: transaction.denom.clone(),
+            balance,
+            required,
+        });
+    }
+    Ok(())
+}
+
+struct CachedAccount {
+    account: Account,
+    fetched_at: Instant,
+}
+
+/// Balances and nonces fetched from the ledger, kept for `ttl` and advanced locally as this
+/// node commits transactions so that they stay current between two fetches
+pub struct AccountCache {
+    ledger: LedgerClient,
+    ttl: Duration,
+    accounts: HashMap<String, CachedAccount>,
+    /// Senders of committed transactions whose fee payments this node is still making, with
+    /// the number of such transactions
+    paying_fees: HashMap<String, usize>,
+}
+
+impl AccountCache {
+    pub fn new(ledger: LedgerClient, ttl: Duration) -> Self {
+        AccountCache { ledger, ttl, accounts: HashMap::new(), paying_fees: HashMap::new() }
+    }
+
+    fn cached(&self, address: &str) -> Option<Account> {
+        self.accounts
+            .get(address)
+            .filter(|cached| cached.fetched_at.elapsed() < self.ttl)
+            .map(|cached| cached.account.clone())
+    }
+
+    fn insert(&mut self, account: Account) {
+        self.accounts.insert(account.address.clone(), CachedAccount { account, fetched_at: Instant::now() });
+    }
+
+    /// Drop the cached accounts of the senders of committed transactions, whose fee payments
+    /// take further nonces and balance
+    pub fn apply_committed(&mut self, transactions: &[TransactionData]) {
+        for transaction in transactions {
+            self.invalidate(transaction.sender.as_str());
+        }
+    }
+
+    /// Drop an account so that the next lookup reads it from the ledger again
+    pub fn invalidate(&mut self, address: &str) {
+        self.accounts.remove(address);
+    }
+
+    /// Note that this node is about to pay the fee of a committed transaction of `address`,
+    /// holding back its next transactions until `end_fee_payments`
+    pub fn begin_fee_payments(&mut self, address: &str) {
+        *self.paying_fees.entry(address.to_string()).or_insert(0) += 1;
+    }
+
+    /// Note that a fee payment run for `address` is over; the account is read from the ledger again
+    pub fn end_fee_payments(&mut self, address: &str) {
+        if let Some(count) = self.paying_fees.get_mut(address) {
+            *count -= 1;
+            if *count == 0 {
+                self.paying_fees.remove(address);
+            }
+        }
+        self.invalidate(address);
+    }
+
+    /// Reject a transaction of a sender whose fee payments are under way
+    pub fn check_not_paying_fees(&self, address: &str) -> Result<(), AccountError> {
+        if self.paying_fees.contains_key(address) {
+            return Err(AccountError::FeesPending { address: address.to_string() });
+        }
+        Ok(())
+    }
+}
+
+/// The account of `address`, from the cache while it is fresh, from the ledger otherwise
+pub async fn load_account(cache: &Arc<Mutex<AccountCache>>, address: &str) -> Result<Account, AccountError> {
+    let ledger = {
+        let cache = cache.lock().await;
+        if let Some(account) = cache.cached(address) {
+            return Ok(account);
+        }
+        cache.ledger.clone()
+    };
+
+    let account = ledger.account(address).await.map_err(|e| AccountError::Ledger(e.to_string()))?;
+    cache.lock().await.insert(account.clone());
+    Ok(account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::AddressFormat;
+    use crate::validation::{ validate_data, DataField, Meta, Metadata };
+
+    const ALICE: &str = "synnq0000000000000000000000000000000000000001";
+    const BOB: &str = "synnq0000000000000000000000000000000000000002";
+
+    fn transaction(sender: &str, nonce: u64, amount: &str, fee: &str) -> TransactionData {
+        TransactionData {
+            transaction_type: "payment".to_string(),
+            sender: sender.parse().unwrap(),
+            private_key: "0".repeat(64),
+            receiver: BOB.parse().unwrap(),
+            amount: amount.parse().unwrap(),
+            denom: "SYNNQ".to_string(),
+            fee: fee.parse().unwrap(),
+            nonce,
+            flags: 0,
+            data_type: "text".to_string(),
+            data: DataField { data: "invoice 42".to_string() },
+            metadata: Metadata { meta: Meta { value: "test".to_string() } },
+            model_type: "default_model".to_string(),
+        }
+    }
+
+    fn account(nonce: u64, balance: &str) -> Account {
+        Account {
+            address: ALICE.to_string(),
+            nonce,
+            balances: HashMap::from([("SYNNQ".to_string(), balance.parse().unwrap())]),
+        }
+    }
+
+    #[test]
+    fn accepts_the_next_nonce_within_the_balance() {
+        assert!(check_transaction(&account(3, "10"), &[], &transaction(ALICE, 3, "9", "1")).is_ok());
+    }
+
+    #[test]
+    fn rejects_reused_and_skipped_nonces() {
+        let result = check_transaction(&account(3, "10"), &[], &transaction(ALICE, 2, "1", "0.1"));
+        assert!(matches!(result, Err(AccountError::NonceReused { nonce: 2, expected: 3, .. })));
+        let result = check_transaction(&account(3, "10"), &[], &transaction(ALICE, 4, "1", "0.1"));
+        assert!(matches!(result, Err(AccountError::NonceGap { nonce: 4, expected: 3, .. })));
+    }
+
+    #[test]
+    fn balance_covers_amount_and_fee() {
+        assert!(check_transaction(&account(0, "10"), &[], &transaction(ALICE, 0, "9.5", "0.5")).is_ok());
+        let result = check_transaction(&account(0, "10"), &[], &transaction(ALICE, 0, "9.5", "0.50000001"));
+        match result {
+            Err(AccountError::InsufficientFunds { balance, required, .. }) => {
+                assert_eq!(balance, "10".parse().unwrap());
+                assert_eq!(required, "10.00000001".parse().unwrap());
+            }
+            other => panic!("expected insufficient funds, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn waits_for_pending_transactions_of_the_sender() {
+        let pending = vec![transaction(ALICE, 3, "1", "0.1")];
+        let result = check_transaction(&account(3, "10"), &pending, &transaction(ALICE, 4, "1", "0.1"));
+        assert!(matches!(result, Err(AccountError::FeesPending { .. })));
+    }
+
+    #[test]
+    fn ignores_applied_and_other_senders_transactions() {
+        let pending = vec![
+            // Already applied by the ledger
+            transaction(ALICE, 1, "5", "0.1"),
+            transaction(BOB, 7, "5", "0.1"),
+        ];
+        assert!(check_transaction(&account(3, "10"), &pending, &transaction(ALICE, 3, "6", "0.1")).is_ok());
+    }
+
+    #[test]
+    fn fixtures_pass_validation() {
+        let format = AddressFormat::new("synnq");
+        let data = serde_json::to_value(transaction(ALICE, 0, "1", "0.1")).unwrap();
+        assert!(futures::executor::block_on(validate_data(&data, &format)));
+    }
+
+    #[test]
+    fn fee_payments_hold_back_the_sender() {
+        let mut cache = AccountCache::new(LedgerClient::new("http://localhost"), Duration::from_secs(10));
+        cache.begin_fee_payments(ALICE);
+        cache.begin_fee_payments(ALICE);
+        cache.end_fee_payments(ALICE);
+        assert!(matches!(cache.check_not_paying_fees(ALICE), Err(AccountError::FeesPending { .. })));
+        assert!(cache.check_not_paying_fees(BOB).is_ok());
+        cache.end_fee_payments(ALICE);
+        assert!(cache.check_not_paying_fees(ALICE).is_ok());
+    }
+}
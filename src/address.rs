@@ -0,0 +1,219 @@
+use crate::config::Config;
+use crate::keymanager::generate_address;
+use serde::{ Deserialize, Deserializer, Serialize, Serializer };
+use sha2::{ Digest, Sha256 };
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Hex characters of the public key hash in an address
+const HASH_HEX_LEN: usize = 40;
+/// Hex characters of the optional checksum following the hash
+const CHECKSUM_HEX_LEN: usize = 8;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AddressError {
+    #[error(
+        "Invalid address {0}: expected a prefix followed by 40 hex characters and an optional 8 hex character checksum"
+    )]
+    InvalidFormat(String),
+    #[error("Invalid address {0}: the prefix must be made of letters and digits")]
+    InvalidPrefix(String),
+    #[error("Address {address} does not have the network prefix {expected}")]
+    WrongPrefix {
+        address: String,
+        expected: String,
+    },
+    #[error("Address {0} has an invalid checksum")]
+    BadChecksum(String),
+}
+
+/// An account address as derived by `keymanager::generate_address`: a lowercase network
+/// prefix followed by the hex of the first 20 bytes of the SHA-256 of the public key,
+/// optionally followed by a checksum of both
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Address(String);
+
+/// First 4 bytes of the SHA-256 of the prefix and hash, in hex
+fn checksum(prefix: &str, hash: &str) -> String {
+    let digest = Sha256::digest(format!("{}{}", prefix, hash).as_bytes());
+    hex::encode(&digest[..CHECKSUM_HEX_LEN / 2])
+}
+
+fn is_hex(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+impl Address {
+    fn new(prefix: &str, rest: &str, address: &str) -> Result<Address, AddressError> {
+        if prefix.is_empty() || !prefix.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(AddressError::InvalidPrefix(address.to_string()));
+        }
+        match rest.len() {
+            HASH_HEX_LEN if is_hex(rest) => {}
+            len if len == HASH_HEX_LEN + CHECKSUM_HEX_LEN && is_hex(rest) => {
+                let (hash, sum) = rest.split_at(HASH_HEX_LEN);
+                if checksum(prefix, hash) != sum {
+                    return Err(AddressError::BadChecksum(address.to_string()));
+                }
+            }
+            _ => {
+                return Err(AddressError::InvalidFormat(address.to_string()));
+            }
+        }
+        Ok(Address(format!("{}{}", prefix, rest)))
+    }
+
+    /// Parse an address that must carry `prefix`
+    pub fn parse_with_prefix(address: &str, prefix: &str) -> Result<Address, AddressError> {
+        let lowercase = address.to_lowercase();
+        let prefix = prefix.to_lowercase();
+        match lowercase.strip_prefix(&prefix) {
+            Some(rest) => Address::new(&prefix, rest, address),
+            None =>
+                Err(AddressError::WrongPrefix {
+                    address: address.to_string(),
+                    expected: prefix,
+                }),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    /// Parse an address with any prefix. When the last 48 characters are hex after a valid
+    /// prefix they are read as a hash and checksum, which must match; otherwise the prefix is
+    /// what precedes the last 40. A prefix ending in 8 hex digits is therefore only readable
+    /// with `parse_with_prefix`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowercase = s.to_lowercase();
+        let checksummed_len = HASH_HEX_LEN + CHECKSUM_HEX_LEN;
+        if lowercase.len() > checksummed_len && lowercase.is_char_boundary(lowercase.len() - checksummed_len) {
+            let (prefix, rest) = lowercase.split_at(lowercase.len() - checksummed_len);
+            match Address::new(prefix, rest, s) {
+                Err(AddressError::BadChecksum(address)) => {
+                    return Err(AddressError::BadChecksum(address));
+                }
+                Ok(address) => {
+                    return Ok(address);
+                }
+                Err(_) => {}
+            }
+        }
+        if lowercase.len() <= HASH_HEX_LEN || !lowercase.is_char_boundary(lowercase.len() - HASH_HEX_LEN) {
+            return Err(AddressError::InvalidFormat(s.to_string()));
+        }
+        let (prefix, rest) = lowercase.split_at(lowercase.len() - HASH_HEX_LEN);
+        Address::new(prefix, rest, s)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let address = String::deserialize(deserializer)?;
+        address.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The network prefix this node expects addresses to carry, set as `address_prefix` in `config.json`
+pub struct AddressFormat {
+    prefix: String,
+}
+
+impl AddressFormat {
+    pub fn from_config(config: &Config) -> Self {
+        AddressFormat::new(&config.address_prefix)
+    }
+
+    pub fn new(prefix: &str) -> Self {
+        AddressFormat { prefix: prefix.to_lowercase() }
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn parse(&self, address: &str) -> Result<Address, AddressError> {
+        Address::parse_with_prefix(address, &self.prefix)
+    }
+
+    /// Whether `address` is this network's address of the wallet with the hex `public_key`
+    pub fn is_owned_by(&self, address: &Address, public_key: &str) -> bool {
+        address.as_str()
+            .strip_prefix(self.prefix.as_str())
+            .map_or(false, |rest| rest[..HASH_HEX_LEN] == generate_address(&public_key.to_lowercase()))
+    }
+
+    /// Check an address parsed with any prefix is on this node's network
+    pub fn check(&self, address: &Address) -> Result<(), AddressError> {
+        self.parse(address.as_str()).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn checksummed(prefix: &str) -> String {
+        format!("{}{}{}", prefix, HASH, checksum(prefix, HASH))
+    }
+
+    #[test]
+    fn parses_plain_and_checksummed_addresses() {
+        let plain = format!("synnq{}", HASH);
+        assert_eq!(plain.parse::<Address>().unwrap().as_str(), plain);
+        assert_eq!(plain.to_uppercase().parse::<Address>().unwrap().as_str(), plain);
+
+        let address = checksummed("synnq");
+        assert_eq!(address.parse::<Address>().unwrap().as_str(), address);
+        assert_eq!(address.to_uppercase().parse::<Address>().unwrap().as_str(), address);
+    }
+
+    #[test]
+    fn rejects_a_bad_checksum_instead_of_reading_a_longer_prefix() {
+        let address = format!("synnq{}{}", HASH, "00000000");
+        assert_ne!(checksum("synnq", HASH), "00000000");
+        assert_eq!(address.parse::<Address>(), Err(AddressError::BadChecksum(address.clone())));
+        assert_eq!(
+            Address::parse_with_prefix(&address, "synnq"),
+            Err(AddressError::BadChecksum(address.clone()))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        assert!(matches!(HASH.parse::<Address>(), Err(AddressError::InvalidFormat(_))));
+        assert!(matches!(format!("synnq{}", &HASH[1..]).parse::<Address>(), Err(AddressError::InvalidFormat(_))));
+        assert!(matches!(format!("syn-q{}", HASH).parse::<Address>(), Err(AddressError::InvalidPrefix(_))));
+        assert!(matches!(format!("synnq{}g", &HASH[1..]).parse::<Address>(), Err(AddressError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn checks_the_network_prefix() {
+        let format = AddressFormat::new("synnq");
+        let own = checksummed("synnq").parse::<Address>().unwrap();
+        let other = checksummed("other").parse::<Address>().unwrap();
+        assert_eq!(format.check(&own), Ok(()));
+        assert!(matches!(format.check(&other), Err(AddressError::WrongPrefix { .. })));
+        assert!(matches!(format.parse(&format!("other{}", HASH)), Err(AddressError::WrongPrefix { .. })));
+    }
+}
@@ -0,0 +1,330 @@
+use crate::consensus::precommit_message;
+use crate::epoch::ValidatorSet;
+use crate::node::node::unix_timestamp;
+use crate::node::signer::verify_signature;
+use std::collections::HashSet;
+use crate::storage::Storage;
+use anyhow::{ anyhow, Result };
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+use sha2::{ Digest, Sha256 };
+
+const BATCH_PREFIX: &str = "batch:";
+const TX_PREFIX: &str = "tx:";
+/// Index from a transaction hash to the height of the batch committing it
+const TX_BATCH_PREFIX: &str = "tx_batch:";
+const BATCH_TIP_KEY: &str = "batch_tip";
+const EPOCH_PREFIX: &str = "epoch:";
+/// Previous hash of the first batch in the log
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BatchSignature {
+    pub node_id: String,
+    /// Consensus round in which the validator precommitted the batch
+    #[serde(default)]
+    pub round: u32,
+    /// Hex signature over the validator's precommit for the header hash
+    pub signature: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BatchHeader {
+    pub height: u64,
+    pub previous_hash: String,
+    pub merkle_root: String,
+    pub timestamp: u64,
+    pub proposer_id: String,
+    /// Epoch whose validator set committed the batch
+    #[serde(default)]
+    pub epoch: u64,
+    /// Validator set taking over after this batch, set on the last batch of an epoch
+    #[serde(default)]
+    pub next_validators: Option<ValidatorSet>,
+    pub quorum_signatures: Vec<BatchSignature>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Batch {
+    pub header: BatchHeader,
+    pub tx_hashes: Vec<String>,
+}
+
+impl BatchHeader {
+    /// Hex SHA-256 of every header field except the signatures, which sign this hash
+    pub fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.height.to_be_bytes());
+        hasher.update(self.previous_hash.as_bytes());
+        hasher.update(self.merkle_root.as_bytes());
+        hasher.update(self.timestamp.to_be_bytes());
+        hasher.update(self.proposer_id.as_bytes());
+        // Only hashed when set, so that batches from before epochs keep their hashes
+        if self.epoch != 0 || self.next_validators.is_some() {
+            hasher.update(self.epoch.to_be_bytes());
+            if let Some(next_validators) = &self.next_validators {
+                hasher.update(serde_json::to_string(next_validators).unwrap().as_bytes());
+            }
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    /// Check the batch carries precommits from distinct members of its epoch's validator set
+    /// holding a quorum of its voting weight
+    pub fn verify_signatures(&self, validators: &ValidatorSet) -> bool {
+        if self.epoch != validators.epoch {
+            return false;
+        }
+
+        let hash = self.hash();
+        let mut signers = HashSet::new();
+        for signature in &self.quorum_signatures {
+            let message = precommit_message(self.epoch, self.height, signature.round, &hash);
+            let valid = validators
+                .public_key(&signature.node_id)
+                .map_or(false, |public_key| {
+                    verify_signature(public_key, &message, &signature.signature)
+                });
+            if !valid {
+                return false;
+            }
+            signers.insert(signature.node_id.as_str());
+        }
+        let weight: u64 = signers
+            .iter()
+            .map(|id| validators.weight(id))
+            .sum();
+        weight >= validators.quorum()
+    }
+}
+
+impl Batch {
+    /// Check the batch's own consistency and that it extends `previous`
+    pub fn verify_link(&self, previous: Option<&BatchHeader>) -> Result<()> {
+        let (expected_height, expected_previous) = match previous {
+            Some(previous) => (previous.height + 1, previous.hash()),
+            None => (1, GENESIS_HASH.to_string()),
+        };
+
+        if self.header.height != expected_height {
+            return Err(anyhow!("Expected batch height {}, got {}", expected_height, self.header.height));
+        }
+        if self.header.previous_hash != expected_previous {
+            return Err(anyhow!("Batch {} does not extend the previous batch", self.header.height));
+        }
+        if self.header.merkle_root != merkle_root(&self.tx_hashes) {
+            return Err(anyhow!("Batch {} has an invalid Merkle root", self.header.height));
+        }
+        Ok(())
+    }
+}
+
+/// Merkle root over hex transaction hashes, duplicating the last node of odd levels
+pub fn merkle_root(tx_hashes: &[String]) -> String {
+    if tx_hashes.is_empty() {
+        return hex::encode(Sha256::digest(b""));
+    }
+
+    let mut level: Vec<Vec<u8>> = tx_hashes
+        .iter()
+        .map(|hash| hex::decode(hash).unwrap_or_else(|_| hash.as_bytes().to_vec()))
+        .collect();
+
+    while level.len() > 1 {
+        if level.len() % 2 == 1 {
+            level.push(level[level.len() - 1].clone());
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let mut hasher = Sha256::new();
+                hasher.update(&pair[0]);
+                hasher.update(&pair[1]);
+                hasher.finalize().to_vec()
+            })
+            .collect();
+    }
+
+    hex::encode(&level[0])
+}
+
+/// Append-only, hash-chained log of the batches committed by consensus
+pub struct BatchLog {
+    storage: Storage,
+    tip: Option<BatchHeader>,
+    /// Validator set of every epoch so far, in epoch order
+    validator_sets: Vec<ValidatorSet>,
+}
+
+impl BatchLog {
+    pub fn load(storage: Storage) -> Self {
+        let tip = storage
+            .get_data(BATCH_TIP_KEY)
+            .and_then(|height| height.parse::<u64>().ok())
+            .and_then(|height| Self::read_batch(&storage, height))
+            .map(|batch| batch.header);
+        let validator_sets = storage
+            .scan_prefix(EPOCH_PREFIX)
+            .into_iter()
+            .filter_map(|(_, value)| serde_json::from_str(&value).ok())
+            .collect();
+
+        BatchLog { storage, tip, validator_sets }
+    }
+
+    fn epoch_key(epoch: u64) -> String {
+        format!("{}{:020}", EPOCH_PREFIX, epoch)
+    }
+
+    fn store_validator_set(&mut self, validators: ValidatorSet) {
+        self.storage.store_data(
+            &Self::epoch_key(validators.epoch),
+            &serde_json::to_string(&validators).unwrap()
+        );
+        println!(
+            "Validator set of epoch {} takes over at height {} with {} validators",
+            validators.epoch,
+            validators.start_height,
+            validators.validators.len()
+        );
+        self.validator_sets.push(validators);
+    }
+
+    /// Record the first epoch's validator set, unless the log already has one
+    pub fn init_genesis(&mut self, validators: ValidatorSet) {
+        if self.validator_sets.is_empty() {
+            self.store_validator_set(validators);
+        }
+    }
+
+    pub fn has_genesis(&self) -> bool {
+        !self.validator_sets.is_empty()
+    }
+
+    /// The validator set voting on `height`
+    pub fn validator_set(&self, height: u64) -> Option<&ValidatorSet> {
+        self.validator_sets
+            .iter()
+            .rev()
+            .find(|validators| validators.start_height <= height)
+    }
+
+    pub fn validator_set_for_epoch(&self, epoch: u64) -> Option<&ValidatorSet> {
+        self.validator_sets.iter().find(|validators| validators.epoch == epoch)
+    }
+
+    pub fn validator_sets(&self) -> &[ValidatorSet] {
+        &self.validator_sets
+    }
+
+    pub fn height(&self) -> u64 {
+        self.tip.as_ref().map_or(0, |tip| tip.height)
+    }
+
+    fn batch_key(height: u64) -> String {
+        // Zero-padded so that prefix scans return batches in height order
+        format!("{}{:020}", BATCH_PREFIX, height)
+    }
+
+    fn read_batch(storage: &Storage, height: u64) -> Option<Batch> {
+        storage
+            .get_data(&Self::batch_key(height))
+            .and_then(|value| serde_json::from_str(&value).ok())
+    }
+
+    pub fn get(&self, height: u64) -> Option<Batch> {
+        Self::read_batch(&self.storage, height)
+    }
+
+    /// Batches with heights in `from..=to`, stopping at the first missing one
+    pub fn range(&self, from: u64, to: u64) -> Vec<Batch> {
+        (from.max(1)..=to.min(self.height()))
+            .map_while(|height| self.get(height))
+            .collect()
+    }
+
+    pub fn get_transaction(&self, tx_hash: &str) -> Option<Value> {
+        self.storage
+            .get_data(&format!("{}{}", TX_PREFIX, tx_hash))
+            .and_then(|value| serde_json::from_str(&value).ok())
+    }
+
+    /// The batch that committed a transaction
+    pub fn batch_of(&self, tx_hash: &str) -> Option<Batch> {
+        self.storage
+            .get_data(&format!("{}{}", TX_BATCH_PREFIX, tx_hash))
+            .and_then(|height| height.parse::<u64>().ok())
+            .and_then(|height| self.get(height))
+    }
+
+    /// Header for a batch of `tx_hashes` on top of the current tip, to be proposed by `proposer_id`
+    pub fn next_header(
+        &self,
+        tx_hashes: &[String],
+        proposer_id: &str,
+        next_validators: Option<ValidatorSet>
+    ) -> BatchHeader {
+        let height = self.height() + 1;
+        BatchHeader {
+            height,
+            previous_hash: self.tip.as_ref().map_or(GENESIS_HASH.to_string(), |tip| tip.hash()),
+            merkle_root: merkle_root(tx_hashes),
+            timestamp: unix_timestamp(),
+            proposer_id: proposer_id.to_string(),
+            epoch: self.validator_set(height).map_or(0, |validators| validators.epoch),
+            next_validators,
+            quorum_signatures: vec![],
+        }
+    }
+
+    /// Persist a batch and its transactions after checking it extends the current tip
+    pub fn append(&mut self, batch: Batch, transactions: Vec<(String, Value)>) -> Result<()> {
+        batch.verify_link(self.tip.as_ref())?;
+
+        let height = batch.header.height;
+        let validators = self
+            .validator_set(height)
+            .ok_or_else(|| anyhow!("No validator set for batch {}", height))?;
+        if batch.header.epoch != validators.epoch {
+            return Err(anyhow!("Batch {} is not from epoch {}", height, validators.epoch));
+        }
+        if let Some(next_validators) = &batch.header.next_validators {
+            next_validators.verify_successor(validators, height + 1)?;
+        }
+
+        for (tx_hash, data) in &transactions {
+            self.storage.store_data(&format!("{}{}", TX_PREFIX, tx_hash), &data.to_string());
+            self.storage.store_data(
+                &format!("{}{}", TX_BATCH_PREFIX, tx_hash),
+                &batch.header.height.to_string()
+            );
+        }
+        self.storage.store_data(
+            &Self::batch_key(batch.header.height),
+            &serde_json::to_string(&batch)?
+        );
+        self.storage.store_data(BATCH_TIP_KEY, &batch.header.height.to_string());
+        if let Some(next_validators) = batch.header.next_validators.clone() {
+            self.store_validator_set(next_validators);
+        }
+
+        println!(
+            "Appended batch {} with {} transactions",
+            batch.header.height,
+            batch.tx_hashes.len()
+        );
+        self.tip = Some(batch.header);
+        Ok(())
+    }
+
+    /// Walk the whole log and check every hash link and Merkle root
+    pub fn verify(&self) -> Result<()> {
+        let mut previous: Option<BatchHeader> = None;
+        for height in 1..=self.height() {
+            let batch = self.get(height).ok_or_else(|| anyhow!("Batch {} is missing", height))?;
+            batch.verify_link(previous.as_ref())?;
+            previous = Some(batch.header);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+use crate::address::AddressFormat;
+use crate::certificate::QuorumCertificate;
+use crate::epoch::ValidatorSet;
+use crate::node::node::{ unix_timestamp, Node, NodeList };
+use crate::node::signer::{ verify_signature, NodeSigner };
+use crate::storage::Storage;
+use crate::validation::{ transaction_hash, validate_data };
+use anyhow::{ anyhow, Result };
+use futures::future::join_all;
+use reqwest::Client;
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+
+const OUTBOX_PREFIX: &str = "outbox:";
+const RECEIVED_PREFIX: &str = "broadcast:";
+const REJECTED_PREFIX: &str = "rejected_broadcast:";
+/// Interval at which the outbox is scanned for due deliveries
+const DELIVERY_INTERVAL: Duration = Duration::from_secs(1);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Upper bound of the exponential retry backoff in seconds
+const MAX_BACKOFF_SECS: u64 = 300;
+/// Deliveries are given up (but kept for inspection) after this many attempts
+pub const MAX_DELIVERY_ATTEMPTS: u32 = 50;
+
+/// A validated transaction gossiped from its origin through the network
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BroadcastMessage {
+    /// The transaction hash, used to deduplicate and acknowledge deliveries
+    pub message_id: String,
+    pub origin_id: String,
+    /// The node that sent this copy of the message
+    pub relayed_by: String,
+    /// Remaining number of gossip hops
+    pub ttl: u32,
+    pub transaction: Value,
+    /// Proof that a quorum of validators approved the transaction at the origin
+    pub certificate: QuorumCertificate,
+    /// Signature of `relayed_by` over the envelope
+    pub signature: String,
+}
+
+impl BroadcastMessage {
+    fn signing_message(&self) -> Vec<u8> {
+        format!(
+            "broadcast:{}:{}:{}:{}",
+            self.message_id,
+            self.origin_id,
+            self.relayed_by,
+            self.ttl
+        ).into_bytes()
+    }
+
+    /// Sign the envelope as the node relaying it
+    pub fn sign(&mut self, signer: &NodeSigner) -> Result<()> {
+        self.relayed_by = signer.node_id().to_string();
+        self.signature = signer.sign(&self.signing_message())?;
+        Ok(())
+    }
+}
+
+/// A broadcast refused by this node, kept for inspection
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RejectedBroadcast {
+    pub message_id: String,
+    pub origin_id: String,
+    pub relayed_by: String,
+    pub reason: String,
+    pub rejected_at: u64,
+}
+
+/// Check the sender's signature, the transaction hash and contents, and the quorum
+/// certificate of an incoming broadcast against the validator set that committed it
+pub async fn verify_broadcast(
+    message: &BroadcastMessage,
+    node_list: &Arc<Mutex<NodeList>>,
+    validators: &ValidatorSet,
+    address_format: &AddressFormat
+) -> Result<()> {
+    {
+        let node_list = node_list.lock().await;
+        let sender = node_list
+            .find_node_by_uuid(&message.relayed_by)
+            .ok_or_else(|| anyhow!("Unknown sender {}", message.relayed_by))?;
+        if !verify_signature(&sender.public_key, &message.signing_message(), &message.signature) {
+            return Err(anyhow!("Invalid signature from sender {}", message.relayed_by));
+        }
+
+        if transaction_hash(&message.transaction) != message.message_id {
+            return Err(anyhow!("Transaction does not match message id {}", message.message_id));
+        }
+
+        message.certificate.verify(&message.message_id, validators)?;
+    }
+
+    if !validate_data(&message.transaction, address_format).await {
+        return Err(anyhow!("Transaction {} failed validation", message.message_id));
+    }
+    Ok(())
+}
+
+pub fn store_rejected(storage: &Storage, message: &BroadcastMessage, reason: &str) {
+    let record = RejectedBroadcast {
+        message_id: message.message_id.clone(),
+        origin_id: message.origin_id.clone(),
+        relayed_by: message.relayed_by.clone(),
+        reason: reason.to_string(),
+        rejected_at: unix_timestamp(),
+    };
+    storage.store_data(
+        &format!("{}{}:{}", REJECTED_PREFIX, message.message_id, message.relayed_by),
+        &serde_json::to_string(&record).unwrap()
+    );
+}
+
+pub fn rejected(storage: &Storage) -> Vec<RejectedBroadcast> {
+    storage
+        .scan_prefix(REJECTED_PREFIX)
+        .into_iter()
+        .filter_map(|(_, value)| serde_json::from_str(&value).ok())
+        .collect()
+}
+
+/// Receipt returned by `/receive_broadcast`, signed by the receiving node
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BroadcastAck {
+    pub message_id: String,
+    pub node_id: String,
+    pub signature: String,
+}
+
+impl BroadcastAck {
+    fn signing_message(message_id: &str) -> Vec<u8> {
+        format!("ack:{}", message_id).into_bytes()
+    }
+
+    pub fn new(signer: &NodeSigner, message_id: &str) -> Result<Self> {
+        Ok(BroadcastAck {
+            message_id: message_id.to_string(),
+            node_id: signer.node_id().to_string(),
+            signature: signer.sign(&Self::signing_message(message_id))?,
+        })
+    }
+
+    pub fn verify(&self, peer: &Node, message_id: &str) -> bool {
+        self.message_id == message_id &&
+            self.node_id == peer.id &&
+            verify_signature(&peer.public_key, &Self::signing_message(message_id), &self.signature)
+    }
+}
+
+/// Delivery of one message to one peer, persisted until it is acknowledged
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OutboundDelivery {
+    pub peer_id: String,
+    pub message: BroadcastMessage,
+    pub attempts: u32,
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+}
+
+/// Delivery state of an outbox entry, without the transaction it carries
+#[derive(Serialize, Debug)]
+pub struct DeliveryStatus {
+    pub message_id: String,
+    pub origin_id: String,
+    pub peer_id: String,
+    pub attempts: u32,
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+}
+
+impl From<OutboundDelivery> for DeliveryStatus {
+    fn from(delivery: OutboundDelivery) -> Self {
+        DeliveryStatus {
+            message_id: delivery.message.message_id,
+            origin_id: delivery.message.origin_id,
+            peer_id: delivery.peer_id,
+            attempts: delivery.attempts,
+            next_attempt_at: delivery.next_attempt_at,
+            last_error: delivery.last_error,
+            created_at: delivery.created_at,
+        }
+    }
+}
+
+/// Outbound broadcast queue with per-peer delivery tracking
+pub struct Outbox {
+    storage: Storage,
+}
+
+impl Outbox {
+    pub fn new(storage: Storage) -> Self {
+        Outbox { storage }
+    }
+
+    fn key(message_id: &str, peer_id: &str) -> String {
+        format!("{}{}:{}", OUTBOX_PREFIX, message_id, peer_id)
+    }
+
+    fn save(&self, delivery: &OutboundDelivery) {
+        self.storage.store_data(
+            &Self::key(&delivery.message.message_id, &delivery.peer_id),
+            &serde_json::to_string(delivery).unwrap()
+        );
+    }
+
+    /// Queue a message for every peer, due immediately
+    pub fn enqueue(&self, message: &BroadcastMessage, peers: &[Node]) {
+        let now = unix_timestamp();
+        for peer in peers {
+            self.save(
+                &(OutboundDelivery {
+                    peer_id: peer.id.clone(),
+                    message: message.clone(),
+                    attempts: 0,
+                    next_attempt_at: now,
+                    last_error: None,
+                    created_at: now,
+                })
+            );
+        }
+    }
+
+    /// Every delivery that has not been acknowledged yet
+    pub fn pending(&self) -> Vec<OutboundDelivery> {
+        self.storage
+            .scan_prefix(OUTBOX_PREFIX)
+            .into_iter()
+            .filter_map(|(_, value)| serde_json::from_str(&value).ok())
+            .collect()
+    }
+
+    /// Deliveries whose next attempt is due and that have not been given up
+    pub fn due(&self) -> Vec<OutboundDelivery> {
+        let now = unix_timestamp();
+        self.pending()
+            .into_iter()
+            .filter(|delivery| {
+                delivery.attempts < MAX_DELIVERY_ATTEMPTS && delivery.next_attempt_at <= now
+            })
+            .collect()
+    }
+
+    pub fn acknowledge(&self, delivery: &OutboundDelivery) {
+        self.storage.delete_data(&Self::key(&delivery.message.message_id, &delivery.peer_id));
+    }
+
+    /// Record a failed attempt and schedule the next one with exponential backoff
+    pub fn record_failure(&self, mut delivery: OutboundDelivery, error: String) {
+        delivery.attempts += 1;
+        let backoff = (2u64).saturating_pow(delivery.attempts).min(MAX_BACKOFF_SECS);
+        delivery.next_attempt_at = unix_timestamp() + backoff;
+        delivery.last_error = Some(error);
+        self.save(&delivery);
+    }
+}
+
+/// Whether a broadcast with this id has already been received
+pub fn already_received(storage: &Storage, message_id: &str) -> bool {
+    storage.get_data(&format!("{}{}", RECEIVED_PREFIX, message_id)).is_some()
+}
+
+pub fn store_received(storage: &Storage, message: &BroadcastMessage) {
+    storage.store_data(
+        &format!("{}{}", RECEIVED_PREFIX, message.message_id),
+        &message.transaction.to_string()
+    );
+}
+
+/// Background worker delivering due outbox entries and retrying failed ones
+pub async fn run_delivery(outbox: Arc<Mutex<Outbox>>, node_list: Arc<Mutex<NodeList>>) {
+    let client = Client::builder()
+        .timeout(DELIVERY_TIMEOUT) // Set a timeout for each request
+        .build()
+        .expect("Failed to build broadcast client");
+
+    loop {
+        let due = outbox.lock().await.due();
+
+        if !due.is_empty() {
+            let peers = {
+                let node_list_guard = node_list.lock().await;
+                node_list_guard.get_nodes()
+            };
+
+            let results = join_all(
+                due.iter().map(|delivery| {
+                    let peer = peers.iter().find(|peer| peer.id == delivery.peer_id);
+                    let client = &client;
+                    async move {
+                        match peer {
+                            Some(peer) => deliver(client, peer, &delivery.message).await,
+                            None => Err(anyhow!("Node {} is no longer known", delivery.peer_id)),
+                        }
+                    }
+                })
+            ).await;
+
+            let outbox = outbox.lock().await;
+            for (delivery, result) in due.into_iter().zip(results) {
+                match result {
+                    Ok(false) => {
+                        eprintln!(
+                            "Broadcast {} was rejected by node {}, dropping it.",
+                            delivery.message.message_id,
+                            delivery.peer_id
+                        );
+                        outbox.acknowledge(&delivery);
+                    }
+                    Ok(true) => {
+                        println!(
+                            "Broadcast {} acknowledged by node {}.",
+                            delivery.message.message_id,
+                            delivery.peer_id
+                        );
+                        outbox.acknowledge(&delivery);
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "Failed to broadcast {} to node {}: {}",
+                            delivery.message.message_id,
+                            delivery.peer_id,
+                            e
+                        );
+                        outbox.record_failure(delivery, e.to_string());
+                    }
+                }
+            }
+        }
+
+        tokio::time::sleep(DELIVERY_INTERVAL).await;
+    }
+}
+
+/// Post a message to a peer; `Ok(false)` means the peer rejected it as invalid
+async fn deliver(client: &Client, peer: &Node, message: &BroadcastMessage) -> Result<bool> {
+    let res = client.post(peer.url("/receive_broadcast")).json(message).send().await?;
+
+    let status = res.status();
+    if status == reqwest::StatusCode::UNPROCESSABLE_ENTITY {
+        return Ok(false);
+    }
+    if !status.is_success() {
+        let body = res.text().await.unwrap_or_else(|_| "No body".to_string());
+        return Err(anyhow!("Status: {}. Response: {}", status, body));
+    }
+
+    let ack: BroadcastAck = res.json().await?;
+    if !ack.verify(peer, &message.message_id) {
+        return Err(anyhow!("Invalid acknowledgement"));
+    }
+    Ok(true)
+}
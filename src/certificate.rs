@@ -0,0 +1,43 @@
+use crate::batch::{ merkle_root, Batch, BatchHeader, BatchLog };
+use crate::epoch::ValidatorSet;
+use anyhow::{ anyhow, Result };
+use serde::{ Deserialize, Serialize };
+
+/// Proof that a transaction was committed by consensus: the header of the batch
+/// containing it, carrying a quorum of precommit signatures, and the batch's transactions
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QuorumCertificate {
+    pub header: BatchHeader,
+    pub tx_hashes: Vec<String>,
+}
+
+impl QuorumCertificate {
+    pub fn new(batch: &Batch) -> Self {
+        QuorumCertificate {
+            header: batch.header.clone(),
+            tx_hashes: batch.tx_hashes.clone(),
+        }
+    }
+
+    /// The certificate of a transaction committed in the local batch log
+    pub fn for_transaction(batch_log: &BatchLog, tx_hash: &str) -> Option<Self> {
+        batch_log.batch_of(tx_hash).map(|batch| Self::new(&batch))
+    }
+
+    /// Check the certificate's batch contains `tx_hash` and was precommitted by a quorum
+    /// of the validator set of its epoch
+    pub fn verify(&self, tx_hash: &str, validators: &ValidatorSet) -> Result<()> {
+        if !self.tx_hashes.iter().any(|hash| hash == tx_hash) {
+            return Err(anyhow!("Certificate does not cover transaction {}", tx_hash));
+        }
+        if self.header.merkle_root != merkle_root(&self.tx_hashes) {
+            return Err(anyhow!("Certificate transactions do not match the batch Merkle root"));
+        }
+        if !self.header.verify_signatures(validators) {
+            return Err(
+                anyhow!("Batch {} lacks a quorum of valid precommits", self.header.height)
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use crate::address::{ Address, AddressFormat };
+use crate::config::Config;
+use crate::keymanager::generate_key_pair_from_mnemonic;
+use crate::keymanager::hd::DerivationPath;
+use std::io::{ self, Error, ErrorKind };
+use zeroize::Zeroizing;
+
+const WALLET_USAGE: &str =
+    "Usage: synnq_val wallet recover [--prefix <prefix>] [--path <derivation path> | --account <n>] [--set-wallet-address]";
+
+fn invalid_input(message: impl ToString) -> Error {
+    Error::new(ErrorKind::InvalidInput, message.to_string())
+}
+
+/// Run a `synnq_val wallet` subcommand instead of the node
+pub fn run_wallet(args: &[String], config_file: &str) -> io::Result<()> {
+    match args.first().map(String::as_str) {
+        Some("recover") => recover_wallet(&args[1..], config_file),
+        _ => Err(invalid_input(WALLET_USAGE)),
+    }
+}
+
+fn prompt_secret(message: &str) -> io::Result<Zeroizing<String>> {
+    println!("{}", message);
+    let mut input = Zeroizing::new(String::new());
+    io::stdin().read_line(&mut input)?;
+    Ok(Zeroizing::new(input.trim().to_string()))
+}
+
+/// Derive a wallet's keys from its mnemonic and BIP39 passphrase, read from stdin, at an optional
+/// derivation path, and print them, optionally making the wallet the node's `wallet_address`
+fn recover_wallet(args: &[String], config_file: &str) -> io::Result<()> {
+    let mut prefix = None;
+    let mut path = None;
+    let mut account = None;
+    let mut set_wallet_address = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--prefix" => {
+                prefix = Some(args.next().ok_or_else(|| invalid_input(WALLET_USAGE))?.clone());
+            }
+            "--path" => {
+                path = Some(args.next().ok_or_else(|| invalid_input(WALLET_USAGE))?.clone());
+            }
+            "--account" => {
+                let value = args.next().ok_or_else(|| invalid_input(WALLET_USAGE))?;
+                account = Some(value.parse::<u32>().map_err(|_| invalid_input(WALLET_USAGE))?);
+            }
+            "--set-wallet-address" => {
+                set_wallet_address = true;
+            }
+            _ => {
+                return Err(invalid_input(WALLET_USAGE));
+            }
+        }
+    }
+
+    let path = DerivationPath::select(path.as_deref(), account).map_err(invalid_input)?;
+    let mut config = Config::load(config_file)?;
+    let prefix = prefix.unwrap_or_else(|| config.address_prefix.clone());
+    let mnemonic = prompt_secret("Enter the mnemonic phrase: ")?;
+    let passphrase = prompt_secret("Enter the BIP39 passphrase (leave empty for none): ")?;
+
+    let (public_key, private_key, address) = generate_key_pair_from_mnemonic(
+        &mnemonic,
+        &passphrase,
+        &prefix,
+        path.as_ref()
+    ).map_err(invalid_input)?;
+    let private_key = Zeroizing::new(private_key);
+    let address = Address::parse_with_prefix(&address, &prefix).map_err(invalid_input)?;
+
+    if let Some(path) = &path {
+        println!("Derivation path: {}", path);
+    }
+    println!("Address: {}", address);
+    println!("Public key: {}", public_key);
+    println!("Private key: {}", private_key.as_str());
+
+    if set_wallet_address {
+        AddressFormat::from_config(&config).check(&address).map_err(invalid_input)?;
+        config.wallet_address = Some(address.to_string());
+        config.save(config_file)?;
+        println!("Set {} as the node's wallet_address in {}", address, config_file);
+    }
+    Ok(())
+}
@@ -1,13 +1,139 @@
 use serde::{ Serialize, Deserialize };
+use crate::fee_policy::FeePolicyConfig;
+use crate::denom::{ default_denoms, Denom };
+use crate::epoch::Validator;
 use uuid::Uuid;
 use std::fs;
 use std::io::{ self, ErrorKind, Result as IoResult };
 
+/// The `wallet_address` the running node is paid its fees to, and the config file it is saved in
+pub struct NodeWallet {
+    pub config_file: String,
+    pub address: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub uuid: String,
     pub address: String,
     pub wallet_address: Option<String>,
+    /// Node ids allowed to register through `/register_node`; any node may register when unset
+    #[serde(default)]
+    pub registration_allowlist: Option<Vec<String>>,
+    /// Maximum `/register_node` requests accepted per source IP per minute
+    #[serde(default = "default_registration_rate_limit")]
+    pub registration_rate_limit: u32,
+    /// Maximum number of pending transactions kept in the mempool
+    #[serde(default = "default_mempool_max_size")]
+    pub mempool_max_size: usize,
+    /// Maximum number of transactions proposed per batch
+    #[serde(default = "default_mempool_batch_size")]
+    pub mempool_batch_size: usize,
+    /// Time a consensus step may take in round 0 before it times out, in milliseconds;
+    /// every later round waits half as long again
+    #[serde(default = "default_consensus_timeout_ms")]
+    pub consensus_timeout_ms: u64,
+    /// Number of batches committed by each validator set before the next one takes over
+    #[serde(default = "default_epoch_length")]
+    pub epoch_length: u64,
+    /// Number of random peers each node forwards a new broadcast to
+    #[serde(default = "default_gossip_fanout")]
+    pub gossip_fanout: usize,
+    /// Maximum number of hops a broadcast travels
+    #[serde(default = "default_gossip_ttl")]
+    pub gossip_ttl: u32,
+    /// Reputation score below which a sufficiently observed peer is excluded
+    #[serde(default = "default_reputation_min_score")]
+    pub reputation_min_score: f64,
+    /// Share of each transaction fee paid to the batch's proposer; the rest is split among the
+    /// validators in its quorum certificate pro rata to their stake
+    #[serde(default = "default_proposer_fee_share")]
+    pub proposer_fee_share: f64,
+    /// Denominations transactions may be made in; transactions in any other are rejected
+    #[serde(default = "default_denoms")]
+    pub denoms: Vec<Denom>,
+    /// Minimum fees charged for submitted transactions
+    #[serde(default)]
+    pub fee_policy: FeePolicyConfig,
+    /// Base URL of the ledger API that bonded stake, balances and nonces are checked against
+    #[serde(default = "default_ledger_url")]
+    pub ledger_url: String,
+    /// Stake a validator's wallet must have bonded to register and to join a validator set
+    #[serde(default = "default_min_stake")]
+    pub min_stake: u64,
+    /// Time withdrawn stake stays liable for misbehaviour, in seconds
+    #[serde(default = "default_unbonding_period_secs")]
+    pub unbonding_period_secs: u64,
+    /// Network prefix of account addresses, including `wallet_address`
+    #[serde(default = "default_address_prefix")]
+    pub address_prefix: String,
+    /// Only create wallets whose private key is stored encrypted in the local keystore, never
+    /// returning it
+    #[serde(default)]
+    pub wallet_keys_encrypted_only: bool,
+    /// Time an account's balance and nonce fetched from the ledger are reused, in seconds
+    #[serde(default = "default_account_cache_ttl_secs")]
+    pub account_cache_ttl_secs: u64,
+    /// Validators of the first epoch, the same on every node of the network; when unset the set
+    /// is taken from the peers, or built from the known nodes by a node without peers
+    #[serde(default)]
+    pub genesis_validators: Option<Vec<Validator>>,
+}
+
+fn default_registration_rate_limit() -> u32 {
+    5
+}
+
+fn default_mempool_max_size() -> usize {
+    10_000
+}
+
+fn default_mempool_batch_size() -> usize {
+    50
+}
+
+fn default_consensus_timeout_ms() -> u64 {
+    3_000
+}
+
+fn default_epoch_length() -> u64 {
+    100
+}
+
+fn default_gossip_fanout() -> usize {
+    3
+}
+
+fn default_gossip_ttl() -> u32 {
+    6
+}
+
+fn default_reputation_min_score() -> f64 {
+    0.5
+}
+
+fn default_proposer_fee_share() -> f64 {
+    0.5
+}
+
+fn default_ledger_url() -> String {
+    "https://rest.synnq.io".to_string()
+}
+
+fn default_min_stake() -> u64 {
+    10_000
+}
+
+fn default_unbonding_period_secs() -> u64 {
+    21 * 24 * 60 * 60
+}
+
+fn default_address_prefix() -> String {
+    "synnq".to_string()
+}
+
+fn default_account_cache_ttl_secs() -> u64 {
+    10
 }
 
 impl Config {
@@ -39,6 +165,12 @@ impl Config {
         }
     }
 
+    /// Read the configuration from an existing file, without prompting for or creating anything
+    pub fn read(config_file: &str) -> IoResult<Self> {
+        let contents = fs::read_to_string(config_file)?;
+        serde_json::from_str(&contents).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
     /// Create a new configuration file
     fn create_new_config(config_file: &str) -> IoResult<Self> {
         let new_uuid = Uuid::new_v4().to_string();
@@ -49,6 +181,25 @@ impl Config {
             uuid: new_uuid,
             address: new_address,
             wallet_address: Some(new_wallet_address),
+            registration_allowlist: None,
+            registration_rate_limit: default_registration_rate_limit(),
+            mempool_max_size: default_mempool_max_size(),
+            mempool_batch_size: default_mempool_batch_size(),
+            consensus_timeout_ms: default_consensus_timeout_ms(),
+            epoch_length: default_epoch_length(),
+            gossip_fanout: default_gossip_fanout(),
+            gossip_ttl: default_gossip_ttl(),
+            reputation_min_score: default_reputation_min_score(),
+            proposer_fee_share: default_proposer_fee_share(),
+            denoms: default_denoms(),
+            fee_policy: FeePolicyConfig::default(),
+            ledger_url: default_ledger_url(),
+            min_stake: default_min_stake(),
+            unbonding_period_secs: default_unbonding_period_secs(),
+            address_prefix: default_address_prefix(),
+            wallet_keys_encrypted_only: false,
+            account_cache_ttl_secs: default_account_cache_ttl_secs(),
+            genesis_validators: None,
         };
 
         new_config.save(config_file)?; // Save the newly created config
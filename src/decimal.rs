@@ -0,0 +1,285 @@
+use serde::de::{ self, Deserializer, Visitor };
+use serde::{ Deserialize, Serialize, Serializer };
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Largest number of fractional digits a `Decimal` can carry
+pub const MAX_SCALE: u32 = 18;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DecimalError {
+    #[error("Invalid decimal amount: {0}")]
+    Invalid(String),
+    #[error("Amount {0} has more than {1} decimal places")]
+    TooPrecise(String, u32),
+    #[error("Amount overflows")]
+    Overflow,
+}
+
+/// Non-negative fixed-point decimal amount: `units` of `10^-scale`.
+///
+/// Amounts are read from JSON numbers or strings. Whole amounts are written as JSON integers and
+/// others as exact decimal strings, since a JSON float would round them; arithmetic is exact on
+/// the units.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Decimal {
+    units: u128,
+    scale: u32,
+}
+
+impl Decimal {
+    pub fn from_units(units: u128, scale: u32) -> Self {
+        Decimal { units, scale }
+    }
+
+    pub fn from_integer(value: u64) -> Self {
+        Decimal { units: value as u128, scale: 0 }
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.units == 0
+    }
+
+    /// The same amount with `scale` fractional digits, failing if that would lose precision
+    pub fn rescale(&self, scale: u32) -> Result<Decimal, DecimalError> {
+        match scale.cmp(&self.scale) {
+            Ordering::Equal => Ok(*self),
+            Ordering::Greater => {
+                let factor = 10u128.checked_pow(scale - self.scale).ok_or(DecimalError::Overflow)?;
+                let units = self.units.checked_mul(factor).ok_or(DecimalError::Overflow)?;
+                Ok(Decimal { units, scale })
+            }
+            Ordering::Less => {
+                let factor = 10u128.pow(self.scale - scale);
+                if self.units % factor != 0 {
+                    return Err(DecimalError::TooPrecise(self.to_string(), scale));
+                }
+                Ok(Decimal { units: self.units / factor, scale })
+            }
+        }
+    }
+
+    pub fn checked_add(&self, other: &Decimal) -> Result<Decimal, DecimalError> {
+        let scale = self.scale.max(other.scale);
+        let (a, b) = (self.rescale(scale)?, other.rescale(scale)?);
+        let units = a.units.checked_add(b.units).ok_or(DecimalError::Overflow)?;
+        Ok(Decimal { units, scale })
+    }
+
+    pub fn checked_sub(&self, other: &Decimal) -> Result<Decimal, DecimalError> {
+        let scale = self.scale.max(other.scale);
+        let (a, b) = (self.rescale(scale)?, other.rescale(scale)?);
+        let units = a.units.checked_sub(b.units).ok_or(DecimalError::Overflow)?;
+        Ok(Decimal { units, scale })
+    }
+
+    /// `self * numerator / denominator`, rounded down to the amount's scale
+    pub fn mul_ratio(&self, numerator: u64, denominator: u64) -> Decimal {
+        if denominator == 0 {
+            return Decimal { units: 0, scale: self.scale };
+        }
+        let units = self.units
+            .checked_mul(numerator as u128)
+            .map(|product| product / (denominator as u128))
+            .unwrap_or_else(|| (self.units / (denominator as u128)) * (numerator as u128));
+        Decimal { units, scale: self.scale }
+    }
+
+    /// The amount as a whole number, if it has no fractional part and fits a `u64`
+    fn to_integer(self) -> Option<u64> {
+        let factor = 10u128.checked_pow(self.scale)?;
+        if self.units % factor != 0 {
+            return None;
+        }
+        u64::try_from(self.units / factor).ok()
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = DecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DecimalError::Invalid(s.to_string());
+        let (whole, fraction) = match s.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (s, ""),
+        };
+        if
+            whole.is_empty() ||
+            !whole.bytes().all(|b| b.is_ascii_digit()) ||
+            !fraction.bytes().all(|b| b.is_ascii_digit()) ||
+            (s.contains('.') && fraction.is_empty())
+        {
+            return Err(invalid());
+        }
+
+        let fraction = fraction.trim_end_matches('0');
+        let scale = fraction.len() as u32;
+        if scale > MAX_SCALE {
+            return Err(DecimalError::TooPrecise(s.to_string(), MAX_SCALE));
+        }
+        let units = format!("{}{}", whole, fraction).parse::<u128>().map_err(|_| DecimalError::Overflow)?;
+        Ok(Decimal { units, scale })
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.scale == 0 {
+            return write!(f, "{}", self.units);
+        }
+        let factor = 10u128.pow(self.scale);
+        let fraction = format!("{:0width$}", self.units % factor, width = self.scale as usize);
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.is_empty() {
+            write!(f, "{}", self.units / factor)
+        } else {
+            write!(f, "{}.{}", self.units / factor, fraction)
+        }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        match (self.rescale(scale), other.rescale(scale)) {
+            (Ok(a), Ok(b)) => a.units.cmp(&b.units),
+            // Only the amount with fewer digits can overflow when rescaled, so it is the larger one
+            (Err(_), _) => Ordering::Greater,
+            (_, Err(_)) => Ordering::Less,
+        }
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.to_integer() {
+            Some(value) => serializer.serialize_u64(value),
+            None => serializer.serialize_str(&self.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DecimalVisitor;
+
+        impl<'de> Visitor<'de> for DecimalVisitor {
+            type Value = Decimal;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a non-negative decimal number or string")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Decimal, E> {
+                Ok(Decimal::from_integer(value))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Decimal, E> {
+                u64::try_from(value)
+                    .map(Decimal::from_integer)
+                    .map_err(|_| E::custom(DecimalError::Invalid(value.to_string())))
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Decimal, E> {
+                if !value.is_finite() {
+                    return Err(E::custom(DecimalError::Invalid(value.to_string())));
+                }
+                // The shortest representation that reads back as the same f64
+                value.to_string().parse().map_err(E::custom)
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Decimal, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(DecimalVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_plain_decimals() {
+        assert_eq!(decimal("12.50"), Decimal::from_units(125, 1));
+        assert_eq!(decimal("12.50").scale(), 1);
+        assert_eq!(decimal("7"), Decimal::from_integer(7));
+        assert_eq!(decimal("0.000000000000000001"), Decimal::from_units(1, 18));
+        for invalid in ["", ".5", "5.", "-1", "1e3", "1.2.3", " 1"] {
+            assert!(matches!(invalid.parse::<Decimal>(), Err(DecimalError::Invalid(_))), "{:?}", invalid);
+        }
+        assert!(matches!("0.0000000000000000001".parse::<Decimal>(), Err(DecimalError::TooPrecise(..))));
+        assert_eq!("340282366920938463463374607431768211456".parse::<Decimal>(), Err(DecimalError::Overflow));
+    }
+
+    #[test]
+    fn rescales_without_losing_precision() {
+        assert_eq!(decimal("1.5").rescale(8).unwrap(), Decimal::from_units(150_000_000, 8));
+        assert_eq!(decimal("1.50").rescale(1).unwrap().scale(), 1);
+        assert_eq!(decimal("1.25").rescale(1), Err(DecimalError::TooPrecise("1.25".to_string(), 1)));
+        assert_eq!(Decimal::from_units(u128::MAX, 0).rescale(1), Err(DecimalError::Overflow));
+    }
+
+    #[test]
+    fn compares_across_scales() {
+        assert_eq!(decimal("1.0"), decimal("1"));
+        assert!(decimal("1.05") > decimal("1"));
+        assert!(decimal("0.99999999") < decimal("1"));
+        // Rescaling the larger amount overflows, which still orders it first
+        assert!(Decimal::from_units(u128::MAX, 0) > decimal("0.5"));
+        assert!(decimal("0.5") < Decimal::from_units(u128::MAX, 0));
+    }
+
+    #[test]
+    fn serializes_exactly() {
+        assert_eq!(serde_json::to_string(&decimal("1")).unwrap(), "1");
+        assert_eq!(serde_json::to_string(&Decimal::from_units(100_000_000, 8)).unwrap(), "1");
+        assert_eq!(serde_json::to_string(&decimal("0.1")).unwrap(), "\"0.1\"");
+        assert_eq!(
+            serde_json::to_string(&decimal("123456789012345678.123456789")).unwrap(),
+            "\"123456789012345678.123456789\""
+        );
+        assert_eq!(
+            serde_json::to_string(&decimal("100000000000000000000")).unwrap(),
+            "\"100000000000000000000\""
+        );
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        for amount in ["0", "1", "0.1", "12.5", "18446744073709551615", "18446744073709551616", "9.000000000000000001"] {
+            let json = serde_json::to_string(&decimal(amount)).unwrap();
+            assert_eq!(serde_json::from_str::<Decimal>(&json).unwrap(), decimal(amount), "{}", json);
+        }
+        assert_eq!(serde_json::from_str::<Decimal>("0.25").unwrap(), decimal("0.25"));
+        assert_eq!(serde_json::from_str::<Decimal>("\"0.25\"").unwrap(), decimal("0.25"));
+        assert!(serde_json::from_str::<Decimal>("-1").is_err());
+    }
+}
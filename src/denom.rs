@@ -0,0 +1,78 @@
+use crate::config::Config;
+use crate::decimal::{ Decimal, DecimalError };
+use serde::{ Deserialize, Serialize };
+use thiserror::Error;
+
+/// A denomination transactions may be made in, as listed under `denoms` in `config.json`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Denom {
+    pub symbol: String,
+    /// Number of decimal places amounts in this denomination may have
+    pub decimals: u32,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+pub fn default_denoms() -> Vec<Denom> {
+    vec![Denom {
+        symbol: "SYNNQ".to_string(),
+        decimals: 8,
+        enabled: true,
+    }]
+}
+
+#[derive(Debug, Error)]
+pub enum DenomError {
+    #[error("Unknown denom {0}")]
+    Unknown(String),
+    #[error("Denom {0} is disabled")]
+    Disabled(String),
+    #[error("Invalid {field} for denom {denom}: {source}")]
+    InvalidAmount {
+        field: &'static str,
+        denom: String,
+        source: DecimalError,
+    },
+}
+
+/// The denominations this node accepts transactions and pays fees in
+pub struct DenomRegistry {
+    denoms: Vec<Denom>,
+}
+
+impl DenomRegistry {
+    pub fn from_config(config: &Config) -> Self {
+        DenomRegistry { denoms: config.denoms.clone() }
+    }
+
+    pub fn all(&self) -> &[Denom] {
+        &self.denoms
+    }
+
+    /// The denomination with `symbol`, if it is registered and enabled
+    pub fn get(&self, symbol: &str) -> Result<&Denom, DenomError> {
+        let denom = self.denoms
+            .iter()
+            .find(|denom| denom.symbol == symbol)
+            .ok_or_else(|| DenomError::Unknown(symbol.to_string()))?;
+        if !denom.enabled {
+            return Err(DenomError::Disabled(symbol.to_string()));
+        }
+        Ok(denom)
+    }
+
+    /// Bring `field` of a transaction in `symbol` to the denomination's number of decimals,
+    /// rejecting unknown denoms and amounts more precise than the denomination allows
+    pub fn normalize(&self, symbol: &str, field: &'static str, amount: &Decimal) -> Result<Decimal, DenomError> {
+        let denom = self.get(symbol)?;
+        amount.rescale(denom.decimals).map_err(|source| DenomError::InvalidAmount {
+            field,
+            denom: symbol.to_string(),
+            source,
+        })
+    }
+}
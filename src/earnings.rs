@@ -0,0 +1,217 @@
+use crate::decimal::Decimal;
+use crate::node::node::unix_timestamp;
+use crate::storage::Storage;
+use serde::{ Deserialize, Serialize };
+use std::collections::BTreeMap;
+
+const EARNING_PREFIX: &str = "earning:";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeeStatus {
+    /// The fee transaction was accepted by the ledger
+    Paid,
+    /// The ledger rejected the fee transaction or could not be reached
+    Failed,
+}
+
+impl FeeStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FeeStatus::Paid => "paid",
+            FeeStatus::Failed => "failed",
+        }
+    }
+}
+
+/// A fee payment this node sent for a transaction it proposed, to its own wallet or to
+/// another validator of the batch's quorum
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FeeRecord {
+    pub tx_hash: String,
+    /// Validator the share was paid to
+    #[serde(default)]
+    pub node_id: String,
+    pub amount: Decimal,
+    pub denom: String,
+    pub receiver: String,
+    pub status: FeeStatus,
+    /// Why the fee transaction failed
+    pub error: Option<String>,
+    pub timestamp: u64,
+}
+
+impl FeeRecord {
+    pub fn new(
+        tx_hash: &str,
+        node_id: &str,
+        amount: Decimal,
+        denom: &str,
+        receiver: &str,
+        result: Result<(), String>
+    ) -> Self {
+        let (status, error) = match result {
+            Ok(()) => (FeeStatus::Paid, None),
+            Err(e) => (FeeStatus::Failed, Some(e)),
+        };
+        FeeRecord {
+            tx_hash: tx_hash.to_string(),
+            node_id: node_id.to_string(),
+            amount,
+            denom: denom.to_string(),
+            receiver: receiver.to_string(),
+            status,
+            error,
+            timestamp: unix_timestamp(),
+        }
+    }
+}
+
+/// Fees of one denomination within a time range
+#[derive(Serialize, Debug, Default)]
+pub struct DenomTotal {
+    pub denom: String,
+    pub paid: Decimal,
+    pub failed: Decimal,
+    pub transactions: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EarningsReport {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub totals: Vec<DenomTotal>,
+    pub records: Vec<FeeRecord>,
+}
+
+/// Every fee transaction sent by this node, persisted in `Storage` in time order
+pub struct EarningsLedger {
+    storage: Storage,
+}
+
+impl EarningsLedger {
+    pub fn new(storage: Storage) -> Self {
+        EarningsLedger { storage }
+    }
+
+    pub fn record(&self, record: &FeeRecord) {
+        self.storage.store_data(
+            &format!("{}{:020}:{}:{}", EARNING_PREFIX, record.timestamp, record.tx_hash, record.node_id),
+            &serde_json::to_string(record).unwrap()
+        );
+        println!(
+            "Recorded {} fee of {} {} to node {} for transaction {}",
+            record.status.as_str(),
+            record.amount,
+            record.denom,
+            record.node_id,
+            record.tx_hash
+        );
+    }
+
+    /// Records with a timestamp within `from..=to`, oldest first, optionally only those paid
+    /// to `node_id`; open ends are unbounded
+    pub fn range(&self, from: Option<u64>, to: Option<u64>, node_id: Option<&str>) -> Vec<FeeRecord> {
+        self.storage
+            .scan_prefix(EARNING_PREFIX)
+            .into_iter()
+            .filter_map(|(_, value)| serde_json::from_str::<FeeRecord>(&value).ok())
+            .filter(|record| {
+                from.map_or(true, |from| record.timestamp >= from) &&
+                    to.map_or(true, |to| record.timestamp <= to) &&
+                    node_id.map_or(true, |node_id| record.node_id == node_id)
+            })
+            .collect()
+    }
+
+    pub fn report(&self, from: Option<u64>, to: Option<u64>, node_id: Option<&str>) -> EarningsReport {
+        let records = self.range(from, to, node_id);
+        let mut totals: BTreeMap<String, DenomTotal> = BTreeMap::new();
+        for record in &records {
+            let total = totals.entry(record.denom.clone()).or_insert_with(|| DenomTotal {
+                denom: record.denom.clone(),
+                ..DenomTotal::default()
+            });
+            let sum = match record.status {
+                FeeStatus::Paid => &mut total.paid,
+                FeeStatus::Failed => &mut total.failed,
+            };
+            *sum = sum.checked_add(&record.amount).unwrap_or(*sum);
+            total.transactions += 1;
+        }
+
+        EarningsReport {
+            from,
+            to,
+            totals: totals.into_values().collect(),
+            records,
+        }
+    }
+}
+
+/// Render records as CSV with a header row, one fee transaction per line
+pub fn to_csv(records: &[FeeRecord]) -> String {
+    let mut csv = String::from("timestamp,tx_hash,node_id,amount,denom,receiver,status,error\n");
+    for record in records {
+        let fields = [
+            record.timestamp.to_string(),
+            record.tx_hash.clone(),
+            record.node_id.clone(),
+            record.amount.to_string(),
+            record.denom.clone(),
+            record.receiver.clone(),
+            record.status.as_str().to_string(),
+            record.error.clone().unwrap_or_default(),
+        ];
+        let line: Vec<String> = fields
+            .iter()
+            .map(|field| csv_field(field))
+            .collect();
+        csv.push_str(&line.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Quote a field if it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(result: Result<(), String>) -> FeeRecord {
+        FeeRecord {
+            timestamp: 1_700_000_000,
+            ..FeeRecord::new("abc", "node-1", Decimal::from_units(125, 1), "SYNNQ", "synnqwallet", result)
+        }
+    }
+
+    #[test]
+    fn writes_a_header_and_one_line_per_record() {
+        let csv = to_csv(&[record(Ok(()))]);
+        assert_eq!(
+            csv,
+            "timestamp,tx_hash,node_id,amount,denom,receiver,status,error\n\
+             1700000000,abc,node-1,12.5,SYNNQ,synnqwallet,paid,\n"
+        );
+    }
+
+    #[test]
+    fn quotes_fields_with_separators_quotes_and_line_breaks() {
+        let csv = to_csv(&[record(Err("ledger said \"no\", twice\nthen\rtimed out".to_string()))]);
+        assert_eq!(
+            csv.split_once('\n').unwrap().1,
+            "1700000000,abc,node-1,12.5,SYNNQ,synnqwallet,failed,\"ledger said \"\"no\"\", twice\nthen\rtimed out\"\n"
+        );
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("\""), "\"\"\"\"");
+    }
+}
@@ -0,0 +1,115 @@
+use crate::consensus::quorum_weight;
+use crate::node::node::Node;
+use anyhow::{ anyhow, Result };
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+
+/// A validator as registered in a validator set, with the key its votes are checked against
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Validator {
+    pub id: String,
+    pub public_key: String,
+    /// Stake bonded by the validator's wallet when the set was chosen; 0 in sets from before staking
+    #[serde(default)]
+    pub stake: u64,
+}
+
+impl Validator {
+    /// Voting weight of the validator's votes; validators without stake count as one vote each
+    pub fn weight(&self) -> u64 {
+        self.stake.max(1)
+    }
+}
+
+/// The validators voting on heights `start_height..` until the next epoch starts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ValidatorSet {
+    pub epoch: u64,
+    pub start_height: u64,
+    /// Sorted by id
+    pub validators: Vec<Validator>,
+}
+
+impl ValidatorSet {
+    pub fn from_nodes(
+        epoch: u64,
+        start_height: u64,
+        nodes: &[Node],
+        stake: impl Fn(&str) -> u64
+    ) -> Self {
+        let mut validators: Vec<Validator> = nodes
+            .iter()
+            .map(|node| Validator {
+                id: node.id.clone(),
+                public_key: node.public_key.clone(),
+                stake: stake(&node.id),
+            })
+            .collect();
+        validators.sort_by(|a, b| a.id.cmp(&b.id));
+        validators.dedup_by(|a, b| a.id == b.id);
+
+        ValidatorSet { epoch, start_height, validators }
+    }
+
+    /// The first epoch's set, starting at height 1
+    pub fn genesis(mut validators: Vec<Validator>) -> Self {
+        validators.sort_by(|a, b| a.id.cmp(&b.id));
+        validators.dedup_by(|a, b| a.id == b.id);
+        ValidatorSet { epoch: 0, start_height: 1, validators }
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        self.validators
+            .iter()
+            .map(|validator| validator.id.clone())
+            .collect()
+    }
+
+    pub fn public_key(&self, id: &str) -> Option<&str> {
+        self.validators
+            .iter()
+            .find(|validator| validator.id == id)
+            .map(|validator| validator.public_key.as_str())
+    }
+
+    /// Voting weight of `id`, 0 if it is not a member of the set
+    pub fn weight(&self, id: &str) -> u64 {
+        self.validators
+            .iter()
+            .find(|validator| validator.id == id)
+            .map_or(0, Validator::weight)
+    }
+
+    pub fn weights(&self) -> HashMap<String, u64> {
+        self.validators
+            .iter()
+            .map(|validator| (validator.id.clone(), validator.weight()))
+            .collect()
+    }
+
+    /// Voting weight of the distinct validator signatures needed to commit a batch in this epoch
+    pub fn quorum(&self) -> u64 {
+        quorum_weight(self.validators.iter().map(Validator::weight).sum())
+    }
+
+    /// Whether `height` is the last one of this epoch, whose batch must name the next set
+    pub fn is_last_height(&self, height: u64, epoch_length: u64) -> bool {
+        height + 1 == self.start_height + epoch_length.max(1)
+    }
+
+    /// Check this set can follow `previous` from `start_height` on
+    pub fn verify_successor(&self, previous: &ValidatorSet, start_height: u64) -> Result<()> {
+        if self.epoch != previous.epoch + 1 || self.start_height != start_height {
+            return Err(
+                anyhow!("Expected epoch {} from height {}", previous.epoch + 1, start_height)
+            );
+        }
+        if self.validators.is_empty() {
+            return Err(anyhow!("Validator set of epoch {} is empty", self.epoch));
+        }
+        if self.validators.windows(2).any(|pair| pair[0].id >= pair[1].id) {
+            return Err(anyhow!("Validator set of epoch {} is not sorted by id", self.epoch));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+use crate::init::NodeInfo;
+use crate::node::node::{ unix_timestamp, NodeList };
+use crate::node::signer::{ verify_signature, NodeSigner };
+use anyhow::Result;
+use futures::future::join_all;
+use reqwest::Client;
+use serde::{ Deserialize, Serialize };
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::fs;
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+use tracing::info;
+
+/// How long a suspicion stays valid without being refreshed by its reporter
+pub const SUSPICION_TTL_SECS: u64 = 60;
+/// Fraction of the other nodes that must suspect a peer before it is evicted
+pub const EVICTION_QUORUM: f64 = 2.0 / 3.0;
+/// How long the discovery service gets to answer a delete request
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A signed statement by `reporter_id` that it cannot reach `suspect_id`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SuspicionAttestation {
+    pub suspect_id: String,
+    pub reporter_id: String,
+    pub timestamp: u64,
+    pub signature: String,
+}
+
+impl SuspicionAttestation {
+    pub fn new(signer: &NodeSigner, suspect_id: &str) -> Result<Self> {
+        let timestamp = unix_timestamp();
+        let signature = signer.sign(&Self::signing_message(suspect_id, timestamp))?;
+
+        Ok(SuspicionAttestation {
+            suspect_id: suspect_id.to_string(),
+            reporter_id: signer.node_id().to_string(),
+            timestamp,
+            signature,
+        })
+    }
+
+    fn signing_message(suspect_id: &str, timestamp: u64) -> Vec<u8> {
+        format!("suspect:{}:{}", suspect_id, timestamp).into_bytes()
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.timestamp + SUSPICION_TTL_SECS < now
+    }
+
+    /// Check that the reporter is a known node other than the suspect and that it signed this attestation
+    pub fn verify(&self, node_list: &NodeList) -> bool {
+        if self.reporter_id == self.suspect_id || self.is_expired(unix_timestamp()) {
+            return false;
+        }
+
+        match node_list.find_node_by_uuid(&self.reporter_id) {
+            Some(reporter) =>
+                verify_signature(
+                    &reporter.public_key,
+                    &Self::signing_message(&self.suspect_id, self.timestamp),
+                    &self.signature
+                ),
+            None => false,
+        }
+    }
+}
+
+/// Number of attestations needed to evict a node when `eligible` other nodes can vote
+pub fn required_attestations(eligible: usize) -> usize {
+    (((eligible as f64) * EVICTION_QUORUM).ceil() as usize).max(1)
+}
+
+/// Suspicions collected from the local health checker and from peers, keyed by suspect then reporter
+#[derive(Default)]
+pub struct EvictionTracker {
+    suspicions: HashMap<String, HashMap<String, SuspicionAttestation>>,
+}
+
+impl EvictionTracker {
+    pub fn new() -> Self {
+        EvictionTracker::default()
+    }
+
+    pub fn record(&mut self, attestation: SuspicionAttestation) {
+        self.suspicions
+            .entry(attestation.suspect_id.clone())
+            .or_default()
+            .insert(attestation.reporter_id.clone(), attestation);
+    }
+
+    /// Drop a reporter's suspicion, e.g. once the suspect answers health checks again
+    pub fn withdraw(&mut self, suspect_id: &str, reporter_id: &str) {
+        if let Some(reports) = self.suspicions.get_mut(suspect_id) {
+            reports.remove(reporter_id);
+            if reports.is_empty() {
+                self.suspicions.remove(suspect_id);
+            }
+        }
+    }
+
+    fn prune(&mut self) {
+        let now = unix_timestamp();
+        self.suspicions.retain(|_, reports| {
+            reports.retain(|_, attestation| !attestation.is_expired(now));
+            !reports.is_empty()
+        });
+    }
+
+    pub fn all(&mut self) -> Vec<SuspicionAttestation> {
+        self.prune();
+        self.suspicions
+            .values()
+            .flat_map(|reports| reports.values().cloned())
+            .collect()
+    }
+
+    /// Remove and return the suspect's attestations if they reach the eviction quorum
+    pub fn take_if_quorum(
+        &mut self,
+        suspect_id: &str,
+        eligible: usize
+    ) -> Option<Vec<SuspicionAttestation>> {
+        self.prune();
+        let count = self.suspicions.get(suspect_id).map_or(0, |reports| reports.len());
+        if count < required_attestations(eligible) {
+            return None;
+        }
+
+        self.suspicions
+            .remove(suspect_id)
+            .map(|reports| reports.into_values().collect())
+    }
+}
+
+/// Record the local node's suspicion of a peer, share it with the other nodes and
+/// evict the peer if a quorum now agrees
+pub async fn suspect_node(
+    suspect_id: &str,
+    signer: &NodeSigner,
+    tracker: &Arc<Mutex<EvictionTracker>>,
+    node_list: &Arc<Mutex<NodeList>>,
+    client: &Client
+) -> Result<()> {
+    let attestation = SuspicionAttestation::new(signer, suspect_id)?;
+    tracker.lock().await.record(attestation.clone());
+
+    let peers = {
+        let node_list_guard = node_list.lock().await;
+        node_list_guard.get_nodes()
+    };
+
+    join_all(
+        peers
+            .iter()
+            .filter(|peer| peer.id != suspect_id && peer.id != signer.node_id())
+            .map(|peer| async {
+                let result = client
+                    .post(peer.url("/suspicions"))
+                    .timeout(Duration::from_secs(5))
+                    .json(&attestation)
+                    .send().await;
+                if let Err(e) = result {
+                    eprintln!("Failed to share suspicion of {} with node {}: {}", suspect_id, peer.id, e);
+                }
+            })
+    ).await;
+
+    try_evict(suspect_id, tracker, node_list, client).await
+}
+
+/// Accept a suspicion shared by a peer after verifying its signature
+pub async fn receive_attestation(
+    attestation: SuspicionAttestation,
+    tracker: &Arc<Mutex<EvictionTracker>>,
+    node_list: &Arc<Mutex<NodeList>>,
+    client: &Client
+) -> Result<()> {
+    let valid = {
+        let node_list_guard = node_list.lock().await;
+        attestation.verify(&node_list_guard)
+    };
+    if !valid {
+        return Err(anyhow::anyhow!("Invalid suspicion attestation from {}", attestation.reporter_id));
+    }
+
+    let suspect_id = attestation.suspect_id.clone();
+    tracker.lock().await.record(attestation);
+    try_evict(&suspect_id, tracker, node_list, client).await
+}
+
+async fn try_evict(
+    suspect_id: &str,
+    tracker: &Arc<Mutex<EvictionTracker>>,
+    node_list: &Arc<Mutex<NodeList>>,
+    client: &Client
+) -> Result<()> {
+    let eligible = {
+        let node_list_guard = node_list.lock().await;
+        node_list_guard
+            .get_nodes()
+            .iter()
+            .filter(|node| node.id != suspect_id)
+            .count()
+    };
+
+    let attestations = tracker.lock().await.take_if_quorum(suspect_id, eligible);
+    match attestations {
+        Some(attestations) => evict_node(suspect_id, attestations, node_list, client).await,
+        None => Ok(()),
+    }
+}
+
+// Remove the node from the NodeList and call the delete_node endpoint with the quorum's attestations
+async fn evict_node(
+    node_id: &str,
+    attestations: Vec<SuspicionAttestation>,
+    node_list: &Arc<Mutex<NodeList>>,
+    client: &Client
+) -> Result<()> {
+    // Remove the node by UUID and copy the remaining nodes, releasing the lock before any I/O
+    let nodes = {
+        let node_list_guard = node_list.lock().await;
+        if node_list_guard.remove_node_by_uuid(node_id) {
+            info!("Node {} successfully removed from local node list", node_id);
+        } else {
+            eprintln!("Node {} not found in local node list", node_id);
+            return Err(anyhow::anyhow!("Node {} not found", node_id));
+        }
+        node_list_guard.get_nodes()
+    };
+
+    // Update the node_info.json file after removal
+    let node_info = NodeInfo { nodes };
+
+    // Serialize the updated node list to JSON and write it to the file
+    if let Err(e) = fs::write(crate::NODE_INFO_FILE, serde_json::to_string(&node_info)?).await {
+        eprintln!("Failed to update node_info.json: {}", e);
+        return Err(anyhow::anyhow!("Failed to update node_info.json"));
+    }
+
+    // Call the delete_node API to remove the node from the discovery service
+    let delete_node_body = json!({ "id": node_id, "attestations": attestations });
+    let response = client
+        .post("https://synnq-discovery-f77aaphiwa-uc.a.run.app/delete_node")
+        .timeout(DISCOVERY_TIMEOUT)
+        .json(&delete_node_body)
+        .send().await?;
+
+    if response.status().is_success() {
+        info!(
+            "Successfully removed node {} from the discovery service with {} attestations",
+            node_id,
+            attestations.len()
+        );
+    } else {
+        eprintln!(
+            "Failed to remove node {} from the discovery service. Status: {}",
+            node_id,
+            response.status()
+        );
+        return Err(
+            anyhow::anyhow!("Failed to remove node {}. Status: {}", node_id, response.status())
+        );
+    }
+
+    Ok(())
+}
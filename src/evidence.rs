@@ -0,0 +1,354 @@
+use crate::address::AddressFormat;
+use crate::batch::{ merkle_root, Batch, BatchLog };
+use crate::consensus::{ ConsensusMessage, MessageKind, ProposalPayload };
+use crate::node::node::{ unix_timestamp, NodeList };
+use crate::node::signer::verify_signature;
+use crate::storage::Storage;
+use crate::validation::{ has_private_key, redact_transaction, transaction_hash, validate_data };
+use anyhow::{ anyhow, Result };
+use futures::future::join_all;
+use reqwest::Client;
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+use sha2::{ Digest, Sha256 };
+use std::collections::{ HashMap, HashSet };
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+
+const EVIDENCE_PREFIX: &str = "evidence:";
+const EVIDENCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Provable validator misbehaviour, checkable by any node from the signed messages it carries
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Misbehaviour {
+    /// Two different values signed by the same validator for the same height, round and step
+    DoubleVote {
+        first: ConsensusMessage,
+        second: ConsensusMessage,
+    },
+    /// A proposal or vote for a batch containing a transaction that fails validation. Only the
+    /// offending transaction's body is kept; the batch's hashes prove it belongs to the batch.
+    InvalidTransaction {
+        message: ConsensusMessage,
+        batch: Batch,
+        tx_hash: String,
+        transaction: Value,
+    },
+}
+
+impl Misbehaviour {
+    fn message(&self) -> &ConsensusMessage {
+        match self {
+            Misbehaviour::DoubleVote { first, .. } => first,
+            Misbehaviour::InvalidTransaction { message, .. } => message,
+        }
+    }
+
+    /// Identifier shared by every report of the same offence
+    fn key(&self) -> String {
+        let message = self.message();
+        let offence = match self {
+            Misbehaviour::DoubleVote { .. } => "double_vote".to_string(),
+            Misbehaviour::InvalidTransaction { message, .. } =>
+                format!("invalid_transaction:{}", message.value.as_deref().unwrap_or("nil")),
+        };
+        format!(
+            "{}:{}:{}:{}:{:?}",
+            offence,
+            message.sender,
+            message.height,
+            message.round,
+            message.kind
+        )
+    }
+
+    /// Check the signatures against the offender's key in its epoch's validator set and
+    /// that the messages really prove the offence
+    pub async fn verify(&self, batch_log: &Arc<Mutex<BatchLog>>, address_format: &AddressFormat) -> Result<()> {
+        let message = self.message();
+        let public_key = batch_log
+            .lock().await
+            .validator_set_for_epoch(message.epoch)
+            .and_then(|validators| validators.public_key(&message.sender).map(str::to_string));
+        let public_key = public_key.ok_or_else(|| {
+            anyhow!("Node {} is not a validator of epoch {}", message.sender, message.epoch)
+        })?;
+        let signed = |message: &ConsensusMessage| {
+            verify_signature(&public_key, &message.signing_message(), &message.signature)
+        };
+
+        match self {
+            Misbehaviour::DoubleVote { first, second } => {
+                if
+                    first.sender != second.sender ||
+                    first.epoch != second.epoch ||
+                    first.height != second.height ||
+                    first.round != second.round ||
+                    first.kind != second.kind
+                {
+                    return Err(anyhow!("Messages are not for the same step"));
+                }
+                if first.value == second.value {
+                    return Err(anyhow!("Messages do not conflict"));
+                }
+                if !signed(first) || !signed(second) {
+                    return Err(anyhow!("Invalid signature from {}", first.sender));
+                }
+            }
+            Misbehaviour::InvalidTransaction { message, batch, tx_hash, transaction } => {
+                if !signed(message) {
+                    return Err(anyhow!("Invalid signature from {}", message.sender));
+                }
+                if message.value.as_deref() != Some(batch.header.hash().as_str()) {
+                    return Err(anyhow!("Message is not for the given batch"));
+                }
+                if batch.header.merkle_root != merkle_root(&batch.tx_hashes) {
+                    return Err(anyhow!("Batch hashes do not match its Merkle root"));
+                }
+                if !batch.tx_hashes.contains(tx_hash) || &transaction_hash(transaction) != tx_hash {
+                    return Err(anyhow!("Batch does not contain transaction {}", tx_hash));
+                }
+                // A redacted copy has the same hash but never validates, so only the body the
+                // proposer sent proves anything
+                if !has_private_key(transaction) {
+                    return Err(anyhow!("Transaction {} is redacted", tx_hash));
+                }
+                if validate_data(transaction, address_format).await {
+                    return Err(anyhow!("Transaction {} is valid", tx_hash));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A misbehaviour report, kept for a future slashing process
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Evidence {
+    pub id: String,
+    pub offender: String,
+    pub height: u64,
+    pub misbehaviour: Misbehaviour,
+    pub reported_by: String,
+    pub reported_at: u64,
+}
+
+impl Evidence {
+    pub fn new(misbehaviour: Misbehaviour, reported_by: &str) -> Self {
+        let message = misbehaviour.message();
+        Evidence {
+            id: hex::encode(Sha256::digest(misbehaviour.key().as_bytes())),
+            offender: message.sender.clone(),
+            height: message.height,
+            reported_by: reported_by.to_string(),
+            reported_at: unix_timestamp(),
+            misbehaviour,
+        }
+    }
+
+    /// The evidence as served to anyone who asks, with the transaction's private key replaced
+    /// by its hash; only the full copy gossiped between nodes can be verified
+    pub fn redacted(&self) -> Evidence {
+        let mut evidence = self.clone();
+        if let Misbehaviour::InvalidTransaction { transaction, .. } = &mut evidence.misbehaviour {
+            *transaction = redact_transaction(transaction);
+        }
+        evidence
+    }
+
+    /// Check the report matches the offence it carries and that the offence is proven
+    pub async fn verify(&self, batch_log: &Arc<Mutex<BatchLog>>, address_format: &AddressFormat) -> Result<()> {
+        let message = self.misbehaviour.message();
+        if
+            self.id != hex::encode(Sha256::digest(self.misbehaviour.key().as_bytes())) ||
+            self.offender != message.sender ||
+            self.height != message.height
+        {
+            return Err(anyhow!("Evidence does not match its misbehaviour"));
+        }
+        self.misbehaviour.verify(batch_log, address_format).await
+    }
+}
+
+/// Detects misbehaviour in the consensus messages of the current height and persists evidence
+pub struct EvidencePool {
+    storage: Storage,
+    self_id: String,
+    /// First signed message seen from each validator per height, round and step
+    votes: HashMap<(u64, u32, MessageKind, String), ConsensusMessage>,
+    /// Proposed batches found to contain an invalid transaction, with its hash and body, by
+    /// header hash
+    invalid: HashMap<String, (Batch, String, Value)>,
+}
+
+impl EvidencePool {
+    pub fn new(storage: Storage, self_id: &str) -> Self {
+        EvidencePool {
+            storage,
+            self_id: self_id.to_string(),
+            votes: HashMap::new(),
+            invalid: HashMap::new(),
+        }
+    }
+
+    /// Record a verified message, returning evidence if it conflicts with an earlier one
+    /// or supports a batch known to be invalid
+    pub fn observe(&mut self, message: &ConsensusMessage) -> Vec<Evidence> {
+        // Only messages of the current and later heights can still be compared
+        self.votes.retain(|(height, ..), _| *height >= message.height);
+        self.invalid.retain(|_, (batch, ..)| batch.header.height >= message.height);
+
+        let mut evidence = Vec::new();
+        let key = (message.height, message.round, message.kind, message.sender.clone());
+        match self.votes.get(&key) {
+            Some(first) if first.value != message.value => {
+                evidence.push(
+                    Evidence::new(
+                        Misbehaviour::DoubleVote { first: first.clone(), second: message.clone() },
+                        &self.self_id
+                    )
+                );
+            }
+            Some(_) => {}
+            None => {
+                self.votes.insert(key, message.clone());
+            }
+        }
+
+        if
+            let Some((batch, tx_hash, transaction)) = message.value
+                .as_ref()
+                .and_then(|value| self.invalid.get(value))
+        {
+            evidence.push(
+                Evidence::new(
+                    Misbehaviour::InvalidTransaction {
+                        message: message.clone(),
+                        batch: batch.clone(),
+                        tx_hash: tx_hash.clone(),
+                        transaction: transaction.clone(),
+                    },
+                    &self.self_id
+                )
+            );
+        }
+        evidence
+    }
+
+    /// Remember a proposed batch with an invalid transaction, returning evidence against
+    /// every validator that already proposed or voted for it
+    pub fn mark_invalid(&mut self, payload: &ProposalPayload, tx_hash: &str) -> Vec<Evidence> {
+        let transaction = match payload.transactions
+            .iter()
+            .find(|(hash, data)| hash == tx_hash && &transaction_hash(data) == tx_hash) {
+            Some((_, transaction)) => transaction.clone(),
+            None => {
+                return Vec::new();
+            }
+        };
+        let value = payload.batch.header.hash();
+        self.invalid.insert(value.clone(), (payload.batch.clone(), tx_hash.to_string(), transaction.clone()));
+
+        self.votes
+            .values()
+            .filter(|message| message.value.as_deref() == Some(value.as_str()))
+            .map(|message| {
+                Evidence::new(
+                    Misbehaviour::InvalidTransaction {
+                        message: message.clone(),
+                        batch: payload.batch.clone(),
+                        tx_hash: tx_hash.to_string(),
+                        transaction: transaction.clone(),
+                    },
+                    &self.self_id
+                )
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.storage.get_data(&format!("{}{}", EVIDENCE_PREFIX, id)).is_some()
+    }
+
+    /// Persist evidence, returning `false` if the offence was already on record
+    pub fn store(&self, evidence: &Evidence) -> bool {
+        if self.contains(&evidence.id) {
+            return false;
+        }
+        self.storage.store_data(
+            &format!("{}{}", EVIDENCE_PREFIX, evidence.id),
+            &serde_json::to_string(evidence).unwrap()
+        );
+        println!("Recorded evidence {} against node {}", evidence.id, evidence.offender);
+        true
+    }
+
+    /// Nodes with misbehaviour on record
+    pub fn offenders(&self) -> HashSet<String> {
+        self.all()
+            .into_iter()
+            .map(|evidence| evidence.offender)
+            .collect()
+    }
+
+    pub fn all(&self) -> Vec<Evidence> {
+        self.storage
+            .scan_prefix(EVIDENCE_PREFIX)
+            .into_iter()
+            .filter_map(|(_, value)| serde_json::from_str(&value).ok())
+            .collect()
+    }
+}
+
+/// Send evidence to every peer but the local node and the one it came from
+pub async fn share_evidence(
+    evidence: &Evidence,
+    node_list: &Arc<Mutex<NodeList>>,
+    client: &Client,
+    self_id: &str,
+    received_from: Option<&str>
+) {
+    let peers = {
+        let node_list_guard = node_list.lock().await;
+        node_list_guard.get_nodes()
+    };
+
+    join_all(
+        peers
+            .iter()
+            .filter(|peer| peer.id != self_id && Some(peer.id.as_str()) != received_from)
+            .map(|peer| async {
+                let result = client
+                    .post(peer.url("/evidence"))
+                    .timeout(EVIDENCE_TIMEOUT)
+                    .json(evidence)
+                    .send().await;
+                if let Err(e) = result {
+                    eprintln!("Failed to share evidence {} with node {}: {}", evidence.id, peer.id, e);
+                }
+            })
+    ).await;
+}
+
+/// Verify and store evidence gossiped by another node, forwarding it if it is new
+pub async fn receive_evidence(
+    evidence: Evidence,
+    pool: &Arc<Mutex<EvidencePool>>,
+    node_list: &Arc<Mutex<NodeList>>,
+    batch_log: &Arc<Mutex<BatchLog>>,
+    address_format: &AddressFormat,
+    client: &Client,
+    self_id: &str
+) -> Result<()> {
+    if pool.lock().await.contains(&evidence.id) {
+        return Ok(());
+    }
+    evidence.verify(batch_log, address_format).await?;
+
+    if pool.lock().await.store(&evidence) {
+        share_evidence(&evidence, node_list, client, self_id, Some(&evidence.reported_by)).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use crate::config::Config;
+use crate::decimal::Decimal;
+use actix_web::http::StatusCode;
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Minimum fees charged by this node, set under `fee_policy` in `config.json`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FeePolicyConfig {
+    /// Minimum fee of transaction types without an entry in `type_fees`
+    #[serde(default = "default_base_fee")]
+    pub base_fee: u64,
+    /// Minimum fee by `transaction_type`
+    #[serde(default)]
+    pub type_fees: HashMap<String, u64>,
+    /// Fee added per byte of the transaction's JSON encoding
+    #[serde(default)]
+    pub per_byte_fee: u64,
+    /// Lowest minimum fee by `denom`, whatever the type and size of the transaction
+    #[serde(default)]
+    pub denom_min_fees: HashMap<String, u64>,
+    /// Mempool utilization (between 0 and 1) above which the minimum fee starts to rise
+    #[serde(default = "default_congestion_threshold")]
+    pub congestion_threshold: f64,
+    /// Factor the minimum fee is multiplied by when the mempool is full
+    #[serde(default = "default_max_congestion_multiplier")]
+    pub max_congestion_multiplier: f64,
+}
+
+fn default_base_fee() -> u64 {
+    1
+}
+
+fn default_congestion_threshold() -> f64 {
+    0.5
+}
+
+fn default_max_congestion_multiplier() -> f64 {
+    4.0
+}
+
+impl Default for FeePolicyConfig {
+    fn default() -> Self {
+        FeePolicyConfig {
+            base_fee: default_base_fee(),
+            type_fees: HashMap::new(),
+            per_byte_fee: 0,
+            denom_min_fees: HashMap::new(),
+            congestion_threshold: default_congestion_threshold(),
+            max_congestion_multiplier: default_max_congestion_multiplier(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct FeeEstimate {
+    pub transaction_type: String,
+    pub denom: String,
+    pub size: usize,
+    /// Minimum fee before the congestion surcharge
+    pub base_fee: u64,
+    pub mempool_utilization: f64,
+    pub congestion_multiplier: f64,
+    /// Fee a transaction must pay to be accepted right now
+    pub minimum_fee: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum FeeError {
+    #[error("Fee {fee} is below the minimum fee of {minimum_fee} for a {size} byte {transaction_type} transaction in {denom}")]
+    BelowMinimum {
+        fee: Decimal,
+        minimum_fee: u64,
+        transaction_type: String,
+        denom: String,
+        size: usize,
+    },
+}
+
+impl FeeError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            FeeError::BelowMinimum { .. } => StatusCode::PAYMENT_REQUIRED,
+        }
+    }
+
+    /// Machine-readable body of the error response
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            FeeError::BelowMinimum { fee, minimum_fee, transaction_type, denom, size } =>
+                serde_json::json!({
+                    "error": "fee_below_minimum",
+                    "message": self.to_string(),
+                    "fee": fee,
+                    "minimum_fee": minimum_fee,
+                    "transaction_type": transaction_type,
+                    "denom": denom,
+                    "size": size,
+                }),
+        }
+    }
+}
+
+/// Decides the minimum fee a transaction must pay from its type, size and denomination,
+/// raised while the mempool is congested
+pub struct FeePolicy {
+    config: FeePolicyConfig,
+}
+
+impl FeePolicy {
+    pub fn from_config(config: &Config) -> Self {
+        FeePolicy { config: config.fee_policy.clone() }
+    }
+
+    /// Multiplier applied to the minimum fee, rising linearly from 1 at the congestion
+    /// threshold to the maximum when the mempool is full
+    fn congestion_multiplier(&self, utilization: f64) -> f64 {
+        let threshold = self.config.congestion_threshold.clamp(0.0, 1.0);
+        let max = self.config.max_congestion_multiplier.max(1.0);
+        if utilization <= threshold || threshold >= 1.0 {
+            return 1.0;
+        }
+        let congestion = ((utilization - threshold) / (1.0 - threshold)).min(1.0);
+        1.0 + (max - 1.0) * congestion
+    }
+
+    pub fn estimate(&self, transaction_type: &str, denom: &str, size: usize, utilization: f64) -> FeeEstimate {
+        let type_fee = self.config.type_fees
+            .get(transaction_type)
+            .copied()
+            .unwrap_or(self.config.base_fee);
+        let size_fee = type_fee.saturating_add(self.config.per_byte_fee.saturating_mul(size as u64));
+        let base_fee = size_fee.max(self.config.denom_min_fees.get(denom).copied().unwrap_or(0));
+
+        let congestion_multiplier = self.congestion_multiplier(utilization);
+        FeeEstimate {
+            transaction_type: transaction_type.to_string(),
+            denom: denom.to_string(),
+            size,
+            base_fee,
+            mempool_utilization: utilization,
+            congestion_multiplier,
+            minimum_fee: ((base_fee as f64) * congestion_multiplier).ceil() as u64,
+        }
+    }
+
+    /// Check a transaction pays at least the current minimum fee
+    pub fn check(
+        &self,
+        transaction_type: &str,
+        denom: &str,
+        size: usize,
+        fee: Decimal,
+        utilization: f64
+    ) -> Result<(), FeeError> {
+        let estimate = self.estimate(transaction_type, denom, size, utilization);
+        if fee < Decimal::from_integer(estimate.minimum_fee) {
+            return Err(FeeError::BelowMinimum {
+                fee,
+                minimum_fee: estimate.minimum_fee,
+                transaction_type: transaction_type.to_string(),
+                denom: denom.to_string(),
+                size,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(congestion_threshold: f64, max_congestion_multiplier: f64) -> FeePolicy {
+        FeePolicy {
+            config: FeePolicyConfig { congestion_threshold, max_congestion_multiplier, ..FeePolicyConfig::default() },
+        }
+    }
+
+    #[test]
+    fn congestion_multiplier_rises_from_one_to_the_maximum() {
+        let policy = policy(0.5, 4.0);
+        assert_eq!(policy.congestion_multiplier(0.0), 1.0);
+        assert_eq!(policy.congestion_multiplier(0.5), 1.0);
+        assert_eq!(policy.congestion_multiplier(0.75), 2.5);
+        assert_eq!(policy.congestion_multiplier(1.0), 4.0);
+        // Utilization past full never raises it further
+        assert_eq!(policy.congestion_multiplier(1.5), 4.0);
+    }
+
+    #[test]
+    fn congestion_multiplier_stays_within_bounds_for_any_config() {
+        for (threshold, max) in [(-1.0, 4.0), (2.0, 4.0), (1.0, 4.0), (0.5, 0.5), (0.0, 1.0), (0.0, 10.0)] {
+            let policy = policy(threshold, max);
+            for utilization in [0.0, 0.1, 0.5, 0.9, 1.0, 2.0] {
+                let multiplier = policy.congestion_multiplier(utilization);
+                assert!((1.0..=max.max(1.0)).contains(&multiplier), "{} at {} {}", multiplier, threshold, max);
+            }
+        }
+        assert_eq!(policy(1.0, 4.0).congestion_multiplier(1.0), 1.0);
+        assert_eq!(policy(0.5, 0.5).congestion_multiplier(1.0), 1.0);
+    }
+
+    #[test]
+    fn minimum_fee_rounds_up_the_congested_fee() {
+        let estimate = policy(0.5, 4.0).estimate("transfer", "SYNNQ", 0, 0.6);
+        assert_eq!(estimate.base_fee, 1);
+        assert_eq!(estimate.minimum_fee, 2);
+    }
+}
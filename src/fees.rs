@@ -0,0 +1,137 @@
+use crate::batch::BatchHeader;
+use crate::decimal::Decimal;
+use crate::epoch::ValidatorSet;
+use std::collections::BTreeSet;
+
+/// The part of a transaction fee owed to one validator
+#[derive(Clone, Debug)]
+pub struct FeeShare {
+    pub node_id: String,
+    pub amount: Decimal,
+}
+
+/// Split `fee` between the batch's proposer, who gets `proposer_share` of it, and the
+/// validators whose precommits are in the batch's quorum certificate, who split the rest
+/// pro rata to their voting weight, rounded down to the fee's smallest unit. The proposer also
+/// gets the rounding remainder, so the shares always add up to the fee.
+pub fn split_fee(
+    fee: Decimal,
+    header: &BatchHeader,
+    validators: &ValidatorSet,
+    proposer_share: f64
+) -> Vec<FeeShare> {
+    let signers: BTreeSet<&str> = header.quorum_signatures
+        .iter()
+        .map(|signature| signature.node_id.as_str())
+        .filter(|id| validators.weight(id) > 0)
+        .collect();
+    let total_weight: u64 = signers
+        .iter()
+        .map(|id| validators.weight(id))
+        .sum();
+
+    // The validators' pool, as a fraction of the fee in millionths
+    let pool_ppm = if total_weight == 0 {
+        0
+    } else {
+        ((1.0 - proposer_share.clamp(0.0, 1.0)) * 1_000_000.0).round() as u64
+    };
+    let pool = fee.mul_ratio(pool_ppm, 1_000_000);
+    let mut shares: Vec<FeeShare> = signers
+        .iter()
+        .filter(|id| **id != header.proposer_id)
+        .map(|id| FeeShare {
+            node_id: id.to_string(),
+            amount: pool.mul_ratio(validators.weight(id), total_weight),
+        })
+        .filter(|share| !share.amount.is_zero())
+        .collect();
+
+    let distributed = shares
+        .iter()
+        .fold(Decimal::from_units(0, fee.scale()), |total, share| {
+            total.checked_add(&share.amount).unwrap_or(total)
+        });
+    shares.insert(0, FeeShare {
+        node_id: header.proposer_id.clone(),
+        amount: fee.checked_sub(&distributed).unwrap_or_default(),
+    });
+    shares
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::BatchSignature;
+    use crate::epoch::Validator;
+
+    fn validators(stakes: &[(&str, u64)]) -> ValidatorSet {
+        ValidatorSet {
+            epoch: 0,
+            start_height: 1,
+            validators: stakes
+                .iter()
+                .map(|(id, stake)| Validator { id: id.to_string(), public_key: String::new(), stake: *stake })
+                .collect(),
+        }
+    }
+
+    fn header(proposer: &str, signers: &[&str]) -> BatchHeader {
+        BatchHeader {
+            height: 1,
+            previous_hash: String::new(),
+            merkle_root: String::new(),
+            timestamp: 0,
+            proposer_id: proposer.to_string(),
+            epoch: 0,
+            next_validators: None,
+            quorum_signatures: signers
+                .iter()
+                .map(|id| BatchSignature { node_id: id.to_string(), round: 0, signature: String::new() })
+                .collect(),
+        }
+    }
+
+    fn total(shares: &[FeeShare], scale: u32) -> Decimal {
+        shares.iter().fold(Decimal::from_units(0, scale), |total, share| total.checked_add(&share.amount).unwrap())
+    }
+
+    #[test]
+    fn gives_the_whole_fee_to_the_proposer_without_signers() {
+        let fee = Decimal::from_units(1_000, 2);
+        let shares = split_fee(fee, &header("a", &[]), &validators(&[("a", 10), ("b", 10)]), 0.5);
+        assert_eq!(shares.len(), 1);
+        assert_eq!(shares[0].node_id, "a");
+        assert_eq!(shares[0].amount, fee);
+    }
+
+    #[test]
+    fn splits_with_a_single_signer() {
+        let fee = Decimal::from_units(1_001, 2);
+        let validators = validators(&[("a", 10), ("b", 10)]);
+
+        let shares = split_fee(fee, &header("a", &["a"]), &validators, 0.5);
+        assert_eq!(shares.len(), 1);
+        assert_eq!(shares[0].amount, fee);
+
+        let shares = split_fee(fee, &header("a", &["b"]), &validators, 0.5);
+        assert_eq!(shares.len(), 2);
+        assert_eq!((shares[1].node_id.as_str(), shares[1].amount), ("b", Decimal::from_units(500, 2)));
+        // The proposer keeps the unit the pool rounds down
+        assert_eq!((shares[0].node_id.as_str(), shares[0].amount), ("a", Decimal::from_units(501, 2)));
+    }
+
+    #[test]
+    fn shares_add_up_to_the_fee() {
+        let validators = validators(&[("a", 3), ("b", 7), ("c", 11), ("d", 0), ("e", 13)]);
+        for units in [1, 7, 100, 99_999, 123_456_789] {
+            for proposer_share in [0.0, 0.25, 0.5, 1.0] {
+                let fee = Decimal::from_units(units, 6);
+                let header = header("b", &["a", "b", "c", "d", "e", "x"]);
+                let shares = split_fee(fee, &header, &validators, proposer_share);
+                assert_eq!(total(&shares, 6), fee, "{} at {}", units, proposer_share);
+                assert!(shares.iter().all(|share| share.node_id != "x"));
+            }
+        }
+    }
+}
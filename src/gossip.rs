@@ -0,0 +1,145 @@
+use crate::broadcast::BroadcastMessage;
+use crate::certificate::QuorumCertificate;
+use crate::config::Config;
+use crate::node::node::Node;
+use crate::node::signer::NodeSigner;
+use crate::reputation::ReputationTracker;
+use anyhow::Result;
+use rand::seq::SliceRandom;
+use serde_json::Value;
+use std::collections::{ HashSet, VecDeque };
+
+/// Number of message ids remembered to stop gossip loops
+const SEEN_CACHE_CAPACITY: usize = 10_000;
+
+/// Bounded set of recently seen message ids, forgetting the oldest first
+pub struct SeenCache {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize) -> Self {
+        SeenCache {
+            ids: HashSet::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains(id)
+    }
+
+    /// Record an id, returning `false` if it had already been seen
+    pub fn insert(&mut self, id: &str) -> bool {
+        if self.ids.contains(id) {
+            return false;
+        }
+
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        self.ids.insert(id.to_string());
+        self.order.push_back(id.to_string());
+        true
+    }
+}
+
+/// Epidemic dissemination of validated transactions: each node forwards a new
+/// message to `fanout` random peers until its TTL runs out
+pub struct Gossip {
+    fanout: usize,
+    ttl: u32,
+    seen: SeenCache,
+}
+
+impl Gossip {
+    pub fn from_config(config: &Config) -> Self {
+        Gossip {
+            fanout: config.gossip_fanout,
+            ttl: config.gossip_ttl,
+            seen: SeenCache::new(SEEN_CACHE_CAPACITY),
+        }
+    }
+
+    /// Mark a message as seen, returning `false` if it is a duplicate
+    pub fn mark_seen(&mut self, message_id: &str) -> bool {
+        self.seen.insert(message_id)
+    }
+
+    pub fn has_seen(&self, message_id: &str) -> bool {
+        self.seen.contains(message_id)
+    }
+
+    /// Build the signed message for a transaction validated by this node
+    pub fn originate(
+        &mut self,
+        tx_hash: &str,
+        transaction: Value,
+        certificate: QuorumCertificate,
+        signer: &NodeSigner
+    ) -> Result<BroadcastMessage> {
+        self.mark_seen(tx_hash);
+        let mut message = BroadcastMessage {
+            message_id: tx_hash.to_string(),
+            origin_id: signer.node_id().to_string(),
+            relayed_by: String::new(),
+            ttl: self.ttl,
+            transaction,
+            certificate,
+            signature: String::new(),
+        };
+        message.sign(signer)?;
+        Ok(message)
+    }
+
+    /// The signed message to forward after receiving `message`, if its TTL allows another hop
+    pub fn relay(&self, message: &BroadcastMessage, signer: &NodeSigner) -> Result<Option<BroadcastMessage>> {
+        if message.ttl <= 1 {
+            return Ok(None);
+        }
+
+        let mut relayed = message.clone();
+        relayed.ttl -= 1;
+        relayed.sign(signer)?;
+        Ok(Some(relayed))
+    }
+
+    /// Pick up to `fanout` random peers weighted by reputation, skipping the local node,
+    /// the origin, the last hop and excluded peers
+    pub fn select_peers(
+        &self,
+        peers: &[Node],
+        message: &BroadcastMessage,
+        self_id: &str,
+        reputation: &ReputationTracker
+    ) -> Vec<Node> {
+        let candidates: Vec<&Node> = peers
+            .iter()
+            .filter(|peer| {
+                peer.id != self_id &&
+                    peer.id != message.origin_id &&
+                    peer.id != message.relayed_by &&
+                    !reputation.is_excluded(&peer.id)
+            })
+            .collect();
+
+        // Keep a small weight so that peers scoring zero can still recover
+        match
+            candidates.choose_multiple_weighted(&mut rand::thread_rng(), self.fanout, |peer| {
+                reputation.score(&peer.id) + 0.01
+            })
+        {
+            Ok(chosen) => chosen.map(|peer| (*peer).clone()).collect(),
+            Err(_) =>
+                candidates
+                    .choose_multiple(&mut rand::thread_rng(), self.fanout)
+                    .map(|peer| (*peer).clone())
+                    .collect(),
+        }
+    }
+}
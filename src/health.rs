@@ -0,0 +1,137 @@
+use crate::node::node::{ Node, NodeList, NodeStatus };
+use crate::node::signer::NodeSigner;
+use crate::eviction::{ suspect_node, EvictionTracker };
+use crate::reputation::ReputationTracker;
+use anyhow::Result;
+use reqwest::Client;
+use serde::{ Deserialize, Serialize };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::{ Duration, Instant };
+
+/// Interval between two health-check rounds
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Timeout for a single `/health` probe
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Liveness information about the local node, served on `GET /health`
+pub struct HealthState {
+    node_id: String,
+    started_at: Instant,
+    ready: AtomicBool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HealthReport {
+    pub version: String,
+    pub node_id: String,
+    pub uptime_secs: u64,
+    pub ready: bool,
+}
+
+impl HealthState {
+    pub fn new(node_id: &str) -> Self {
+        HealthState {
+            node_id: node_id.to_string(),
+            started_at: Instant::now(),
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::SeqCst);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    pub fn report(&self) -> HealthReport {
+        HealthReport {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            node_id: self.node_id.clone(),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            ready: self.is_ready(),
+        }
+    }
+}
+
+/// Probe every known node periodically, updating its health metadata and
+/// reporting it to the eviction quorum once it has been down for
+/// `DOWN_THRESHOLD` consecutive checks
+pub async fn run_health_checks(
+    node_list: Arc<Mutex<NodeList>>,
+    tracker: Arc<Mutex<EvictionTracker>>,
+    reputation: Arc<Mutex<ReputationTracker>>,
+    signer: Arc<NodeSigner>,
+    client: Client
+) {
+    let self_id = signer.node_id().to_string();
+    loop {
+        let nodes = {
+            let node_list_guard = node_list.lock().await;
+            node_list_guard.get_nodes()
+        };
+
+        for node in nodes.into_iter().filter(|node| node.id != self_id) {
+            match probe_node(&node, &client).await {
+                Ok((latency, report)) => {
+                    {
+                        let node_list_guard = node_list.lock().await;
+                        node_list_guard.record_health_success(
+                            &node.id,
+                            latency.as_millis() as u64,
+                            report.ready
+                        );
+                    }
+                    reputation.lock().await.record_probe_success(&node.id, latency.as_millis() as u64);
+                    tracker.lock().await.withdraw(&node.id, &self_id);
+                }
+                Err(e) => {
+                    println!("Node {} failed health check: {}", node.id, e);
+                    reputation.lock().await.record_probe_failure(&node.id);
+                    let status = {
+                        let node_list_guard = node_list.lock().await;
+                        node_list_guard.record_health_failure(&node.id)
+                    };
+
+                    if status == Some(NodeStatus::Down) {
+                        if
+                            let Err(e) = suspect_node(
+                                &node.id,
+                                &signer,
+                                &tracker,
+                                &node_list,
+                                &client
+                            ).await
+                        {
+                            tracing::error!("Failed to report node {} as down: {}", node.id, e);
+                        }
+                    }
+                }
+            }
+        }
+
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+/// Query a node's `/health` endpoint, returning the round-trip time and its report
+pub async fn probe_node(node: &Node, client: &Client) -> Result<(Duration, HealthReport)> {
+    let started = Instant::now();
+    let response = client.get(node.url("/health")).timeout(PROBE_TIMEOUT).send().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("health endpoint responded with {}", response.status()));
+    }
+
+    let report: HealthReport = response.json().await?;
+    if report.node_id != node.id {
+        return Err(
+            anyhow::anyhow!("health endpoint reported node id {} instead of {}", report.node_id, node.id)
+        );
+    }
+
+    Ok((started.elapsed(), report))
+}
@@ -13,6 +13,7 @@ struct RegisterNodeRequest {
     id: String,
     address: String,
     public_key: String,
+    wallet_address: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -83,6 +84,7 @@ pub async fn register_with_discovery_service(
         id: uuid,
         address: address.clone(),
         public_key: node.public_key.clone(),
+        wallet_address: node.wallet_address.clone(),
     };
 
     let response = client.post(&discovery_service_url).json(&request_body).send().await?;
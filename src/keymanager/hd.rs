@@ -0,0 +1,220 @@
+use super::KeyError;
+use hmac::{ Hmac, Mac };
+use sha2::Sha512;
+use std::fmt;
+use std::str::FromStr;
+use zeroize::Zeroize;
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// Offset of hardened indexes; SLIP-0010 only defines hardened derivation for Ed25519
+pub const HARDENED: u32 = 0x8000_0000;
+/// SLIP-0044 coin type of the paths accounts are derived at
+pub const COIN_TYPE: u32 = 0x53594e;
+
+/// A hardened derivation path such as `m/44'/5462350'/0'/0'/0'`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// Path of the `account`-th account: `m/44'/<COIN_TYPE>'/0'/0'/<account>'`
+    pub fn account(account: u32) -> Result<Self, KeyError> {
+        if account >= HARDENED {
+            return Err(KeyError::InvalidPath(format!("account {} is out of range", account)));
+        }
+        Ok(DerivationPath(vec![44 | HARDENED, COIN_TYPE | HARDENED, HARDENED, HARDENED, account | HARDENED]))
+    }
+
+    /// The path requested as either an explicit `path` or an `account` number, if any
+    pub fn select(path: Option<&str>, account: Option<u32>) -> Result<Option<Self>, KeyError> {
+        match (path, account) {
+            (Some(_), Some(_)) =>
+                Err(KeyError::InvalidPath("give either a derivation path or an account, not both".to_string())),
+            (Some(path), None) => path.parse().map(Some),
+            (None, Some(account)) => DerivationPath::account(account).map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = KeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || KeyError::InvalidPath(s.to_string());
+        let mut segments = s.trim().split('/');
+        if segments.next() != Some("m") {
+            return Err(invalid());
+        }
+
+        let mut indexes = Vec::new();
+        for segment in segments {
+            let index = segment
+                .strip_suffix('\'')
+                .or_else(|| segment.strip_suffix('h'))
+                .or_else(|| segment.strip_suffix('H'))
+                .ok_or_else(|| KeyError::NonHardened(s.to_string()))?;
+            let index: u32 = index.parse().map_err(|_| invalid())?;
+            if index >= HARDENED {
+                return Err(invalid());
+            }
+            indexes.push(index | HARDENED);
+        }
+        Ok(DerivationPath(indexes))
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            write!(f, "/{}'", index & !HARDENED)?;
+        }
+        Ok(())
+    }
+}
+
+/// An Ed25519 private key with the chain code its children are derived with
+pub struct ExtendedKey {
+    pub key: [u8; 32],
+    pub chain_code: [u8; 32],
+}
+
+impl Drop for ExtendedKey {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        self.chain_code.zeroize();
+    }
+}
+
+impl ExtendedKey {
+    fn from_hmac(key: &[u8], data: &[&[u8]]) -> Self {
+        let mut mac = HmacSha512::new_from_slice(key).expect("HMAC accepts keys of any length");
+        for part in data {
+            mac.update(part);
+        }
+        let mut output = mac.finalize().into_bytes();
+        let mut extended = ExtendedKey { key: [0; 32], chain_code: [0; 32] };
+        extended.key.copy_from_slice(&output[..32]);
+        extended.chain_code.copy_from_slice(&output[32..]);
+        output.zeroize();
+        extended
+    }
+
+    /// Master key of a BIP39 seed
+    pub fn master(seed: &[u8]) -> Self {
+        ExtendedKey::from_hmac(b"ed25519 seed", &[seed])
+    }
+
+    /// Hardened child at `index`, which must already include the `HARDENED` offset
+    fn child(&self, index: u32) -> Self {
+        ExtendedKey::from_hmac(&self.chain_code, &[&[0], &self.key, &index.to_be_bytes()])
+    }
+
+    /// Key at `path` below the master key of `seed`
+    pub fn derive(seed: &[u8], path: &DerivationPath) -> Self {
+        path.0.iter().fold(ExtendedKey::master(seed), |key, index| key.child(*index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    /// Check the chain code, private key and public key derived at `path` against a SLIP-0010
+    /// test vector
+    fn assert_derives(seed: &str, path: &str, chain_code: &str, private_key: &str, public_key: &str) {
+        let seed = hex::decode(seed).unwrap();
+        let key = ExtendedKey::derive(&seed, &path.parse().unwrap());
+        assert_eq!(hex::encode(key.chain_code), chain_code, "chain code at {}", path);
+        assert_eq!(hex::encode(key.key), private_key, "private key at {}", path);
+        let public = SigningKey::from_bytes(&key.key).verifying_key();
+        // SLIP-0010 prefixes Ed25519 public keys with a zero byte
+        assert_eq!(format!("00{}", hex::encode(public.as_bytes())), public_key, "public key at {}", path);
+    }
+
+    #[test]
+    fn slip10_ed25519_vector_1() {
+        let seed = "000102030405060708090a0b0c0d0e0f";
+        assert_derives(
+            seed,
+            "m",
+            "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb",
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7",
+            "00a4b2856bfec510abab89753fac1ac0e1112364e7d250545963f135f2a33188ed"
+        );
+        assert_derives(
+            seed,
+            "m/0'",
+            "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69",
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+            "008c8a13df77a28f3445213a0f432fde644acaa215fc72dcdf300d5efaa85d350c"
+        );
+        assert_derives(
+            seed,
+            "m/0'/1'",
+            "a320425f77d1b5c2505a6b1b27382b37368ee640e3557c315416801243552f14",
+            "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2",
+            "001932a5270f335bed617d5b935c80aedb1a35bd9fc1e31acafd5372c30f5c1187"
+        );
+        assert_derives(
+            seed,
+            "m/0'/1'/2'",
+            "2e69929e00b5ab250f49c3fb1c12f252de4fed2c1db88387094a0f8c4c9ccd6c",
+            "92a5b23c0b8a99e37d07df3fb9966917f5d06e02ddbd909c7e184371463e9fc9",
+            "00ae98736566d30ed0e9d2f4486a64bc95740d89c7db33f52121f8ea8f76ff0fc1"
+        );
+        assert_derives(
+            seed,
+            "m/0'/1'/2'/2'/1000000000'",
+            "68789923a0cac2cd5a29172a475fe9e0fb14cd6adb5ad98a3fa70333e7afa230",
+            "8f94d394a8e8fd6b1bc2f3f49f5c47e385281d5c17e65324b0f62483e37e8793",
+            "003c24da049451555d51a7014a37337aa4e12d41e485abccfa46b47dfb2af54b7a"
+        );
+    }
+
+    #[test]
+    fn slip10_ed25519_vector_2() {
+        let seed =
+            "fffcf9f6f3f0edeae7e4e1dedbd8d5d2cfccc9c6c3c0bdbab7b4b1aeaba8a5a29f9c999693908d8a8784817e7b7875726f6c696663605d5a5754514e4b484542";
+        assert_derives(
+            seed,
+            "m",
+            "ef70a74db9c3a5af931b5fe73ed8e1a53464133654fd55e7a66f8570b8e33c3b",
+            "171cb88b1b3c1db25add599712e36245d75bc65a1a5c9e18d76f9f2b1eab4012",
+            "008fe9693f8fa62a4305a140b9764c5ee01e455963744fe18204b4fb948249308a"
+        );
+        assert_derives(
+            seed,
+            "m/0'",
+            "0b78a3226f915c082bf118f83618a618ab6dec793752624cbeb622acb562862d",
+            "1559eb2bbec5790b0c65d8693e4d0875b1747f4970ae8b650486ed7470845635",
+            "0086fab68dcb57aa196c77c5f264f215a112c22a912c10d123b0d03c3c28ef1037"
+        );
+        assert_derives(
+            seed,
+            "m/0'/2147483647'",
+            "138f0b2551bcafeca6ff2aa88ba8ed0ed8de070841f0c4ef0165df8181eaad7f",
+            "ea4f5bfe8694d8bb74b7b59404632fd5968b774ed545e810de9c32a4fb4192f4",
+            "005ba3b9ac6e90e83effcd25ac4e58a1365a9e35a3d3ae5eb07b9e4d90bcf7506d"
+        );
+    }
+
+    #[test]
+    fn paths_must_be_hardened() {
+        assert!(matches!("m/44'/0".parse::<DerivationPath>(), Err(KeyError::NonHardened(_))));
+        assert!(matches!("44'/0'".parse::<DerivationPath>(), Err(KeyError::InvalidPath(_))));
+        assert!(matches!("m/2147483648'".parse::<DerivationPath>(), Err(KeyError::InvalidPath(_))));
+        assert_eq!("m/44h/1H/0'".parse::<DerivationPath>().unwrap().to_string(), "m/44'/1'/0'");
+        assert_eq!(DerivationPath::account(3).unwrap().to_string(), format!("m/44'/{}'/0'/0'/3'", COIN_TYPE));
+    }
+
+    #[test]
+    fn accounts_of_one_seed_differ() {
+        let seed = [7u8; 64];
+        let first = ExtendedKey::derive(&seed, &DerivationPath::account(0).unwrap());
+        let second = ExtendedKey::derive(&seed, &DerivationPath::account(1).unwrap());
+        assert_ne!(first.key, second.key);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::node::node::unix_timestamp;
+use crate::storage::Storage;
+use actix_web::http::StatusCode;
+use aes_gcm::aead::{ Aead, KeyInit };
+use aes_gcm::{ Aes256Gcm, Nonce };
+use anyhow::{ anyhow, Result };
+use argon2::Argon2;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{ Deserialize, Serialize };
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+const KEYSTORE_PREFIX: &str = "keystore:";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Error)]
+pub enum KeystoreError {
+    #[error("No key is stored for wallet {0}")]
+    NotFound(String),
+    #[error("Wrong passphrase for wallet {0}")]
+    WrongPassphrase(String),
+    #[error("Stored key of wallet {0} is unreadable: {1}")]
+    Corrupt(String, String),
+}
+
+impl KeystoreError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            KeystoreError::NotFound(_) => StatusCode::NOT_FOUND,
+            KeystoreError::WrongPassphrase(_) => StatusCode::UNAUTHORIZED,
+            KeystoreError::Corrupt(..) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// A wallet's private key, encrypted with AES-256-GCM under a key derived from a passphrase
+/// with Argon2id
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EncryptedKey {
+    pub address: String,
+    pub public_key: String,
+    /// Hex of the encrypted private key and its authentication tag
+    pub ciphertext: String,
+    pub nonce: String,
+    pub salt: String,
+    pub created_at: u64,
+}
+
+impl EncryptedKey {
+    /// Encrypt `private_key` with `passphrase` under a fresh salt and nonce
+    pub fn seal(address: &str, public_key: &str, private_key: &str, passphrase: &str) -> Result<EncryptedKey> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let key = derive_key(passphrase, &salt)?;
+        let cipher = Aes256Gcm::new_from_slice(key.as_ref()).map_err(|e| anyhow!(e.to_string()))?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), private_key.as_bytes())
+            .map_err(|_| anyhow!("Failed to encrypt the private key"))?;
+
+        Ok(EncryptedKey {
+            address: address.to_string(),
+            public_key: public_key.to_string(),
+            ciphertext: hex::encode(ciphertext),
+            nonce: hex::encode(nonce),
+            salt: hex::encode(salt),
+            created_at: unix_timestamp(),
+        })
+    }
+
+    /// Decrypt the private key, which only succeeds with the passphrase it was sealed with
+    pub fn open(&self, passphrase: &str) -> Result<Zeroizing<String>, KeystoreError> {
+        let corrupt = |e: String| KeystoreError::Corrupt(self.address.clone(), e);
+        let salt = hex::decode(&self.salt).map_err(|e| corrupt(e.to_string()))?;
+        let nonce = hex::decode(&self.nonce).map_err(|e| corrupt(e.to_string()))?;
+        let ciphertext = hex::decode(&self.ciphertext).map_err(|e| corrupt(e.to_string()))?;
+        if nonce.len() != NONCE_LEN {
+            return Err(corrupt("invalid nonce length".to_string()));
+        }
+
+        let key = derive_key(passphrase, &salt).map_err(|e| corrupt(e.to_string()))?;
+        let cipher = Aes256Gcm::new_from_slice(key.as_ref()).map_err(|e| corrupt(e.to_string()))?;
+        // The authentication tag only matches under the key the passphrase derives
+        let private_key = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| KeystoreError::WrongPassphrase(self.address.clone()))?;
+        String::from_utf8(private_key)
+            .map(Zeroizing::new)
+            .map_err(|e| corrupt(e.to_string()))
+    }
+}
+
+/// Summary of a stored key, without its ciphertext
+#[derive(Serialize, Debug)]
+pub struct StoredWallet {
+    pub address: String,
+    pub public_key: String,
+    pub created_at: u64,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| anyhow!("Failed to derive the encryption key: {}", e))?;
+    Ok(key)
+}
+
+/// Wallet private keys created by this node, kept encrypted in `Storage`
+pub struct Keystore {
+    storage: Storage,
+    encrypted_only: bool,
+}
+
+impl Keystore {
+    pub fn new(storage: Storage, encrypted_only: bool) -> Self {
+        Keystore { storage, encrypted_only }
+    }
+
+    /// Whether created wallets must be stored encrypted, so their private key is never returned
+    pub fn encrypted_only(&self) -> bool {
+        self.encrypted_only
+    }
+
+    /// Encrypt `private_key` with `passphrase` and store it under `address`
+    pub fn store(&self, address: &str, public_key: &str, private_key: &str, passphrase: &str) -> Result<()> {
+        let entry = EncryptedKey::seal(address, public_key, private_key, passphrase)?;
+        self.storage.store_data(&format!("{}{}", KEYSTORE_PREFIX, address), &serde_json::to_string(&entry)?);
+        println!("Stored the encrypted key of wallet {}", address);
+        Ok(())
+    }
+
+    /// Decrypt the stored private key of `address` with `passphrase`
+    pub fn load(&self, address: &str, passphrase: &str) -> Result<Zeroizing<String>, KeystoreError> {
+        let entry = self.storage
+            .get_data(&format!("{}{}", KEYSTORE_PREFIX, address))
+            .ok_or_else(|| KeystoreError::NotFound(address.to_string()))?;
+        let entry = serde_json::from_str::<EncryptedKey>(&entry).map_err(|e|
+            KeystoreError::Corrupt(address.to_string(), e.to_string())
+        )?;
+        entry.open(passphrase)
+    }
+
+    pub fn all(&self) -> Vec<StoredWallet> {
+        self.storage
+            .scan_prefix(KEYSTORE_PREFIX)
+            .into_iter()
+            .filter_map(|(_, value)| serde_json::from_str::<EncryptedKey>(&value).ok())
+            .map(|entry| StoredWallet {
+                address: entry.address,
+                public_key: entry.public_key,
+                created_at: entry.created_at,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIVATE_KEY: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+
+    #[test]
+    fn opens_with_the_sealing_passphrase_only() {
+        let entry = EncryptedKey::seal("synnqwallet", "public", PRIVATE_KEY, "correct horse").unwrap();
+        assert!(!entry.ciphertext.contains(PRIVATE_KEY));
+        assert_eq!(entry.open("correct horse").unwrap().as_str(), PRIVATE_KEY);
+        assert!(matches!(entry.open("wrong horse"), Err(KeystoreError::WrongPassphrase(_))));
+    }
+
+    #[test]
+    fn reports_a_tampered_entry() {
+        let mut entry = EncryptedKey::seal("synnqwallet", "public", PRIVATE_KEY, "passphrase").unwrap();
+        entry.nonce = "zz".to_string();
+        assert!(matches!(entry.open("passphrase"), Err(KeystoreError::Corrupt(..))));
+    }
+}
@@ -1,12 +1,36 @@
+pub mod hd;
+pub mod keystore;
+
 use rand::RngCore;
 use rand::rngs::OsRng;
-use ed25519_dalek::{SigningKey, SECRET_KEY_LENGTH};
+use ed25519_dalek::{Signature, SigningKey, Verifier, VerifyingKey, SECRET_KEY_LENGTH};
 use bip39::{Mnemonic, Language};
 use sha2::{Sha256, Digest as ShaDigest};
 use hex;
+use hd::{ DerivationPath, ExtendedKey };
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum KeyError {
+    #[error("Invalid mnemonic phrase: {0}")]
+    InvalidMnemonic(String),
+    #[error("Invalid derivation path: {0}")]
+    InvalidPath(String),
+    #[error("Derivation path {0} has non-hardened indexes, which Ed25519 keys cannot be derived at")]
+    NonHardened(String),
+}
+
+/// Signing key of a BIP39 seed: the SLIP-0010 key at `path`, or without a path the seed's first
+/// 32 bytes, as wallets created before HD derivation use
+fn signing_key_from_seed(seed: &[u8], path: Option<&DerivationPath>) -> SigningKey {
+    match path {
+        Some(path) => SigningKey::from_bytes(&ExtendedKey::derive(seed, path).key),
+        None => SigningKey::from_bytes(&seed[0..SECRET_KEY_LENGTH].try_into().expect("slice with incorrect length")),
+    }
+}
 
-/// Generates a new key pair with a prefix and returns the public key, private key, mnemonic phrase, and address.
-pub fn generate_key_pair_with_prefix(prefix: &str) -> (String, String, String, String) {
+/// Generates a new key pair with a prefix, at `path` below the new mnemonic's seed if given, and returns the public key, private key, mnemonic phrase, and address.
+pub fn generate_key_pair_with_prefix(prefix: &str, path: Option<&DerivationPath>) -> (String, String, String, String) {
     // Generate a random 128-bit (16 bytes) entropy
     let mut entropy = [0u8; 32];
     OsRng.fill_bytes(&mut entropy);
@@ -18,9 +42,7 @@ pub fn generate_key_pair_with_prefix(prefix: &str) -> (String, String, String, S
     let seed = mnemonic.to_seed("");
 
     // Generate a signing key using the seed
-    let signing_key = SigningKey::from_bytes(
-        &seed[0..SECRET_KEY_LENGTH].try_into().expect("slice with incorrect length")
-    );
+    let signing_key = signing_key_from_seed(&seed, path);
 
     // Get the verifying key from the signing key, and encode it in hexadecimal format
     let public_key = hex::encode(signing_key.verifying_key().as_bytes());
@@ -35,6 +57,19 @@ pub fn generate_key_pair_with_prefix(prefix: &str) -> (String, String, String, S
     (public_key, private_key, mnemonic.to_string(), address)
 }
 
+/// Whether `signature` is a valid hex Ed25519 signature of `message` by the wallet with the hex `public_key`
+pub fn verify_wallet_signature(public_key: &str, message: &[u8], signature: &str) -> bool {
+    let public_key = match hex::decode(public_key).ok().and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()) {
+        Some(public_key) => public_key,
+        None => return false,
+    };
+    let signature = match hex::decode(signature).ok().and_then(|bytes| Signature::from_slice(&bytes).ok()) {
+        Some(signature) => signature,
+        None => return false,
+    };
+    VerifyingKey::from_bytes(&public_key).map_or(false, |key| key.verify(message, &signature).is_ok())
+}
+
 /// Generates an address from the public key
 pub fn generate_address(public_key: &str) -> String {
     let mut hasher = Sha256::new();
@@ -43,18 +78,23 @@ pub fn generate_address(public_key: &str) -> String {
     hex::encode(&result[..20])
 }
 
-/// Retrieves a key pair and address from a provided mnemonic phrase
-pub fn generate_key_pair_from_mnemonic(mnemonic_phrase: &str, prefix: &str) -> (String, String, String) {
+/// Retrieves a key pair and address from a provided mnemonic phrase and optional BIP39 passphrase, at `path` if given
+pub fn generate_key_pair_from_mnemonic(
+    mnemonic_phrase: &str,
+    passphrase: &str,
+    prefix: &str,
+    path: Option<&DerivationPath>
+) -> Result<(String, String, String), KeyError> {
     // Parse the mnemonic from the provided phrase using the appropriate language
-    let mnemonic = Mnemonic::parse_in(Language::English, mnemonic_phrase).expect("Invalid mnemonic phrase");
+    let mnemonic = Mnemonic::parse_in(Language::English, mnemonic_phrase.trim()).map_err(|e|
+        KeyError::InvalidMnemonic(e.to_string())
+    )?;
 
-    // Derive a seed from the mnemonic
-    let seed = mnemonic.to_seed("");
+    // Derive a seed from the mnemonic and passphrase
+    let seed = mnemonic.to_seed(passphrase);
 
     // Generate a signing key using the seed
-    let signing_key = SigningKey::from_bytes(
-        &seed[0..SECRET_KEY_LENGTH].try_into().expect("slice with incorrect length")
-    );
+    let signing_key = signing_key_from_seed(&seed, path);
 
     // Get the verifying key from the signing key, and encode it in hexadecimal format
     let public_key = hex::encode(signing_key.verifying_key().as_bytes());
@@ -66,5 +106,5 @@ pub fn generate_key_pair_from_mnemonic(mnemonic_phrase: &str, prefix: &str) -> (
     let address = format!("{}{}", prefix.to_lowercase(), generate_address(&public_key));
 
     // Return the public key, private key, and the derived address
-    (public_key, private_key, address)
+    Ok((public_key, private_key, address))
 }
@@ -0,0 +1,76 @@
+use crate::decimal::Decimal;
+use anyhow::{ anyhow, Result };
+use reqwest::{ Client, Response };
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::time::Duration;
+
+const LEDGER_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize, Debug)]
+struct StakeResponse {
+    bonded: u64,
+}
+
+/// An address's state on the ledger
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Account {
+    #[serde(default)]
+    pub address: String,
+    /// Nonce the ledger expects on the address's next transaction
+    pub nonce: u64,
+    /// Spendable balance by denom
+    #[serde(default)]
+    pub balances: HashMap<String, Decimal>,
+}
+
+/// Read access to the accounts and stake on the ledger
+#[derive(Clone)]
+pub struct LedgerClient {
+    client: Client,
+    base_url: String,
+}
+
+impl LedgerClient {
+    pub fn new(base_url: &str) -> Self {
+        LedgerClient {
+            client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Endpoint committed transactions and fee payments are submitted to
+    pub fn transaction_url(&self) -> String {
+        format!("{}/transaction", self.base_url)
+    }
+
+    /// Submit a transaction to the ledger
+    pub async fn post_transaction(&self, transaction: &Value) -> reqwest::Result<Response> {
+        self.client.post(self.transaction_url()).json(transaction).send().await
+    }
+
+    async fn get<T: for<'de> Deserialize<'de>>(&self, path: &str) -> Result<T> {
+        let response = self.client
+            .get(format!("{}/{}", self.base_url, path))
+            .timeout(LEDGER_TIMEOUT)
+            .send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Ledger responded with {}", response.status()));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Amount currently bonded by `wallet_address`
+    pub async fn bonded_stake(&self, wallet_address: &str) -> Result<u64> {
+        let stake: StakeResponse = self.get(&format!("stake/{}", wallet_address)).await?;
+        Ok(stake.bonded)
+    }
+
+    /// Balances and next nonce of `address`
+    pub async fn account(&self, address: &str) -> Result<Account> {
+        let mut account: Account = self.get(&format!("accounts/{}", address)).await?;
+        account.address = address.to_string();
+        Ok(account)
+    }
+}
@@ -6,16 +6,35 @@ mod storage;
 mod config;
 mod init;
 mod keymanager;
+mod health;
+mod eviction;
+mod mempool;
+mod batch;
+mod sync;
+mod broadcast;
+mod gossip;
+mod certificate;
+mod evidence;
+mod reputation;
+mod epoch;
+mod staking;
+mod earnings;
+mod fees;
+mod fee_policy;
+mod decimal;
+mod denom;
+mod ledger;
+mod accounts;
+mod address;
+mod cli;
 
 use actix_web::{ App, HttpServer, web };
-use std::collections::HashMap;
-use tokio::fs;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::time::Duration;
 use std::net::SocketAddr;
 use crate::node::node::{ NodeList, Node };
-use crate::config::Config;
+use crate::config::{ Config, NodeWallet };
 use crate::init::{
     NodeInfo,
     resolve_address,
@@ -24,18 +43,47 @@ use crate::init::{
     validate_address
 };
 use crate::storage::Storage;
+use crate::health::{ HealthState, run_health_checks };
+use crate::eviction::EvictionTracker;
+use crate::node::signer::NodeSigner;
+use crate::network::registration::RegistrationGuard;
+use crate::mempool::Mempool;
+use crate::batch::BatchLog;
+use crate::sync::run_initial_sync;
+use crate::epoch::ValidatorSet;
+use crate::broadcast::{ Outbox, run_delivery };
+use crate::gossip::Gossip;
+use crate::evidence::EvidencePool;
+use crate::reputation::ReputationTracker;
+use crate::staking::{ StakeRegistry, run_stake_checks };
+use crate::ledger::LedgerClient;
+use crate::accounts::AccountCache;
+use crate::address::AddressFormat;
+use crate::keymanager::keystore::Keystore;
+use crate::earnings::EarningsLedger;
+use crate::fee_policy::FeePolicy;
+use crate::denom::DenomRegistry;
+use crate::consensus::{ ConsensusContext, ConsensusEngine, ConsensusState, run_consensus };
 use tracing::info;
 use reqwest::Client;
-use anyhow::Result;
-use serde_json::json;
 
 const NODE_INFO_FILE: &str = "node_info.json";
 const CONFIG_FILE: &str = "config.json";
+const PRIVATE_KEY_FILE: &str = "private_key.pem";
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     tracing_subscriber::fmt().with_max_level(tracing::Level::DEBUG).init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("wallet") {
+        if let Err(e) = cli::run_wallet(&args[1..], CONFIG_FILE) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     info!("Starting application...");
 
     // Load the configuration (UUID and address)
@@ -59,8 +107,12 @@ async fn main() -> std::io::Result<()> {
         }
     }
 
-    // Proceed with the rest of the logic using the wallet address
-    let _wallet_address = config.wallet_address.as_ref().unwrap();
+    // Proceed with the rest of the logic using the wallet address, which fees are paid to
+    let address_format = Arc::new(AddressFormat::from_config(&config));
+    if let Err(e) = address_format.parse(config.wallet_address.as_ref().unwrap()) {
+        eprintln!("Invalid wallet_address in {}: {}", CONFIG_FILE, e);
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()));
+    }
 
     // If the address is not an IP:Port, resolve it using the resolve_address function.
 let server_address = if validate_address(&config.address) {
@@ -98,10 +150,15 @@ let server_address = if validate_address(&config.address) {
 
     let node = {
         let node_list_guard = node_list.lock().await;
-        if let Some(existing_node) = node_list_guard.find_node_by_uuid(&config.uuid) {
-            existing_node.clone()
+        if let Some(mut existing_node) = node_list_guard.find_node_by_uuid(&config.uuid) {
+            // The discovery service may not know the wallet backing our stake
+            if existing_node.wallet_address.is_none() {
+                existing_node.wallet_address = config.wallet_address.clone();
+                node_list_guard.add_node(existing_node.clone());
+            }
+            existing_node
         } else {
-            let new_node = Node::new(&config.address);
+            let new_node = Node::new(&config.address, config.wallet_address.clone());
             register_with_discovery_service(
                 &new_node,
                 config.uuid.clone(),
@@ -116,6 +173,64 @@ let server_address = if validate_address(&config.address) {
     info!("Public Key: {}", node.public_key);
 
     let storage = Arc::new(Mutex::new(Storage::new("database/db")));
+    let (mempool, batch_log, outbox) = {
+        let storage_guard = storage.lock().await;
+        (
+            Arc::new(Mutex::new(Mempool::load(storage_guard.clone(), config.mempool_max_size))),
+            Arc::new(Mutex::new(BatchLog::load(storage_guard.clone()))),
+            Arc::new(Mutex::new(Outbox::new(storage_guard.clone()))),
+        )
+    };
+    if let Err(e) = batch_log.lock().await.verify() {
+        tracing::error!("Local batch log failed verification: {}", e);
+    }
+    if let Some(validators) = &config.genesis_validators {
+        let genesis = ValidatorSet::genesis(validators.clone());
+        let mut batch_log = batch_log.lock().await;
+        batch_log.init_genesis(genesis.clone());
+        if batch_log.validator_set_for_epoch(0) != Some(&genesis) {
+            eprintln!("The local batch log starts from other validators than genesis_validators in {}", CONFIG_FILE);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Mismatched genesis_validators"));
+        }
+    }
+    let gossip = Arc::new(Mutex::new(Gossip::from_config(&config)));
+    let evidence = Arc::new(Mutex::new(EvidencePool::new(storage.lock().await.clone(), &config.uuid)));
+    let reputation = Arc::new(
+        Mutex::new(ReputationTracker::load(storage.lock().await.clone(), config.reputation_min_score))
+    );
+    let stakes = Arc::new(
+        Mutex::new(
+            StakeRegistry::load(
+                storage.lock().await.clone(),
+                config.min_stake,
+                config.unbonding_period_secs
+            )
+        )
+    );
+    let ledger = LedgerClient::new(&config.ledger_url);
+    let accounts = Arc::new(
+        Mutex::new(AccountCache::new(ledger.clone(), Duration::from_secs(config.account_cache_ttl_secs)))
+    );
+    let earnings = Arc::new(Mutex::new(EarningsLedger::new(storage.lock().await.clone())));
+    let fee_policy = Arc::new(FeePolicy::from_config(&config));
+    let denoms = Arc::new(DenomRegistry::from_config(&config));
+    let node_wallet = Arc::new(
+        Mutex::new(NodeWallet {
+            config_file: CONFIG_FILE.to_string(),
+            address: config.wallet_address.clone().unwrap(),
+        })
+    );
+    let keystore = Arc::new(Keystore::new(storage.lock().await.clone(), config.wallet_keys_encrypted_only));
+    let health = Arc::new(HealthState::new(&config.uuid));
+    let eviction_tracker = Arc::new(Mutex::new(EvictionTracker::new()));
+    let registration_guard = Arc::new(Mutex::new(RegistrationGuard::from_config(&config)));
+    let signer = match NodeSigner::load(PRIVATE_KEY_FILE, &config.uuid) {
+        Ok(signer) => Arc::new(signer),
+        Err(e) => {
+            eprintln!("Failed to load node signing key: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, e.to_string()));
+        }
+    };
 
     let node_list_clone = Arc::clone(&node_list);
     let client = Client::new(); // Create a reqwest client for making HTTP requests
@@ -125,9 +240,8 @@ let server_address = if validate_address(&config.address) {
         loop {
             match fetch_and_update_nodes(NODE_INFO_FILE).await {
                 Ok(updated_node_info) => {
-                    let updated_node_list = NodeList::from_nodes(updated_node_info.nodes);
-                    let mut node_list_guard = node_list_clone.lock().await;
-                    *node_list_guard = updated_node_list;
+                    let node_list_guard = node_list_clone.lock().await;
+                    node_list_guard.merge_nodes(updated_node_info.nodes);
                     info!("Node list updated.");
                 }
                 Err(e) => tracing::error!("Failed to update node list: {}", e),
@@ -137,12 +251,85 @@ let server_address = if validate_address(&config.address) {
         }
     });
 
-    // Task to check node availability and remove unreachable nodes after 3 failed cycles
+    // Task to probe peers' health and report them for eviction after repeated failures
     let node_list_clone_for_check = Arc::clone(&node_list);
+    let tracker_clone_for_check = Arc::clone(&eviction_tracker);
+    let reputation_clone_for_check = Arc::clone(&reputation);
+    let signer_clone_for_check = Arc::clone(&signer);
     tokio::spawn(async move {
-        if let Err(e) = check_and_remove_unavailable_nodes(node_list_clone_for_check, client).await {
-            tracing::error!("Error checking and removing nodes: {}", e);
-        }
+        run_health_checks(
+            node_list_clone_for_check,
+            tracker_clone_for_check,
+            reputation_clone_for_check,
+            signer_clone_for_check,
+            client
+        ).await;
+    });
+
+    // Task to re-check the validators' bonded stake against the ledger
+    let stakes_clone_for_check = Arc::clone(&stakes);
+    let ledger_clone_for_check = ledger.clone();
+    tokio::spawn(async move {
+        run_stake_checks(stakes_clone_for_check, ledger_clone_for_check).await;
+    });
+
+    // Task to run consensus on batches of pending transactions from the mempool
+    let consensus = ConsensusContext {
+        state: Arc::new(
+            Mutex::new(
+                ConsensusState::new(
+                    ConsensusEngine::new(&config.uuid, 0, vec![], batch_log.lock().await.height() + 1)
+                )
+            )
+        ),
+        node_list: Arc::clone(&node_list),
+        storage: Arc::clone(&storage),
+        mempool: Arc::clone(&mempool),
+        batch_log: Arc::clone(&batch_log),
+        outbox: Arc::clone(&outbox),
+        gossip: Arc::clone(&gossip),
+        evidence: Arc::clone(&evidence),
+        reputation: Arc::clone(&reputation),
+        stakes: Arc::clone(&stakes),
+        earnings: Arc::clone(&earnings),
+        denoms: Arc::clone(&denoms),
+        address_format: Arc::clone(&address_format),
+        accounts: Arc::clone(&accounts),
+        ledger: ledger.clone(),
+        signer: Arc::clone(&signer),
+        health: Arc::clone(&health),
+        wallet: Arc::clone(&node_wallet),
+        batch_size: config.mempool_batch_size,
+        timeout: Duration::from_millis(config.consensus_timeout_ms),
+        epoch_length: config.epoch_length,
+        proposer_fee_share: config.proposer_fee_share,
+    };
+    let consensus_clone = consensus.clone();
+    tokio::spawn(async move {
+        run_consensus(consensus_clone).await;
+    });
+
+    // Task to deliver queued broadcasts and retry unacknowledged ones
+    let outbox_clone_for_delivery = Arc::clone(&outbox);
+    let node_list_clone_for_delivery = Arc::clone(&node_list);
+    tokio::spawn(async move {
+        run_delivery(outbox_clone_for_delivery, node_list_clone_for_delivery).await;
+    });
+
+    // Task to catch up with the peers' batch logs; the node reports ready once done
+    let batch_log_clone_for_sync = Arc::clone(&batch_log);
+    let node_list_clone_for_sync = Arc::clone(&node_list);
+    let health_clone_for_sync = Arc::clone(&health);
+    let stakes_clone_for_sync = Arc::clone(&stakes);
+    let self_id = config.uuid.clone();
+    tokio::spawn(async move {
+        run_initial_sync(
+            batch_log_clone_for_sync,
+            node_list_clone_for_sync,
+            health_clone_for_sync,
+            stakes_clone_for_sync,
+            self_id
+        ).await;
     });
 
     // Bind and run the server using the resolved or fallback address
@@ -150,131 +337,28 @@ let server_address = if validate_address(&config.address) {
         App::new()
             .app_data(web::Data::new(Arc::clone(&node_list)))
             .app_data(web::Data::new(Arc::clone(&storage)))
+            .app_data(web::Data::new(Arc::clone(&health)))
+            .app_data(web::Data::new(Arc::clone(&eviction_tracker)))
+            .app_data(web::Data::new(Arc::clone(&registration_guard)))
+            .app_data(web::Data::new(Arc::clone(&mempool)))
+            .app_data(web::Data::new(Arc::clone(&batch_log)))
+            .app_data(web::Data::new(Arc::clone(&outbox)))
+            .app_data(web::Data::new(Arc::clone(&gossip)))
+            .app_data(web::Data::new(Arc::clone(&evidence)))
+            .app_data(web::Data::new(Arc::clone(&reputation)))
+            .app_data(web::Data::new(Arc::clone(&stakes)))
+            .app_data(web::Data::new(ledger.clone()))
+            .app_data(web::Data::new(Arc::clone(&earnings)))
+            .app_data(web::Data::new(Arc::clone(&fee_policy)))
+            .app_data(web::Data::new(Arc::clone(&denoms)))
+            .app_data(web::Data::new(Arc::clone(&address_format)))
+            .app_data(web::Data::new(Arc::clone(&keystore)))
+            .app_data(web::Data::new(Arc::clone(&node_wallet)))
+            .app_data(web::Data::new(Arc::clone(&accounts)))
+            .app_data(web::Data::new(Arc::clone(&signer)))
+            .app_data(web::Data::new(consensus.clone()))
             .configure(network::api::init_routes)
     })
         .bind(server_address)?
         .run().await
 }
-
-// Check if nodes are available and remove them after 3 failed checks
-async fn check_and_remove_unavailable_nodes(
-    node_list: Arc<Mutex<NodeList>>,
-    client: Client
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut failed_attempts: HashMap<String, u8> = HashMap::new(); // Track failed attempts (UUID, failed count)
-
-    loop {
-        // Lock the node list to check availability
-        let nodes = {
-            let node_list_guard = node_list.lock().await;
-            node_list_guard.get_nodes().clone()
-        };
-
-        // Check each node's availability
-        for node in nodes {
-            let node_id = node.id.clone();
-            let node_available = check_node_availability(&node, &client).await;
-
-            if !node_available {
-                // Increment the failure count or insert it if not present
-                let count = failed_attempts.entry(node_id.clone()).or_insert(0);
-                *count += 1;
-
-                // If the node has failed 3 times, remove it
-                if *count >= 3 {
-                    // Remove node and send delete request
-                    remove_node(&node_id, &node_list, &client).await?;
-                    info!("Node {} removed after 3 failed attempts", node_id);
-                    failed_attempts.remove(&node_id); // Remove from failed attempts
-                }
-            } else {
-                // If node is available, reset the failure count
-                failed_attempts.remove(&node_id);
-            }
-        }
-
-        // Sleep for 5 seconds before the next cycle
-        tokio::time::sleep(Duration::from_secs(5)).await;
-    }
-}
-
-// Check if the node is available by pinging or validating its availability (mock logic)
-pub async fn check_node_availability(node: &Node, client: &Client) -> bool {
-    // Define a timeout for the request
-    let timeout_duration = Duration::from_secs(5); // Set a 5-second timeout
-
-    // Build the full URL from the node's address (assuming the node runs on HTTP)
-    let node_url = format!("http://{}/nodes", node.address); // Replace `/health` with the actual endpoint if needed
-
-    // Send a request to the node to check availability
-    let response = client
-        .get(&node_url)
-        .timeout(timeout_duration) // Set the timeout for the request
-        .send().await;
-
-    match response {
-        Ok(res) => {
-            // Check if the response status is success (status code 2xx)
-            if res.status().is_success() {
-                // println!("Node {} is available", node.id);
-                true
-            } else {
-                println!("Node {} responded with an error: {}", node.id, res.status());
-                false
-            }
-        }
-        Err(e) => {
-            // If there's an error (e.g., timeout or connection refused)
-            println!("Node {} is unavailable: {}", node.id, e);
-            false
-        }
-    }
-}
-
-// Remove the node from the NodeList and call the delete_node endpoint
-async fn remove_node(
-    node_id: &str,
-    node_list: &Arc<Mutex<NodeList>>,
-    client: &Client
-) -> Result<()> {
-    // Lock the node list and remove the node by UUID
-    let node_list_guard = node_list.lock().await;
-    if node_list_guard.remove_node_by_uuid(node_id) {
-        info!("Node {} successfully removed from local node list", node_id);
-    } else {
-        eprintln!("Node {} not found in local node list", node_id);
-        return Err(anyhow::anyhow!("Node {} not found", node_id));
-    }
-
-    // Update the node_info.json file after removal
-    let nodes = node_list_guard.get_nodes().clone();
-    let node_info = NodeInfo { nodes };
-
-    // Serialize the updated node list to JSON and write it to the file
-    if let Err(e) = fs::write(NODE_INFO_FILE, serde_json::to_string(&node_info)?).await {
-        eprintln!("Failed to update node_info.json: {}", e);
-        return Err(anyhow::anyhow!("Failed to update node_info.json"));
-    }
-
-    // Call the delete_node API to remove the node from the discovery service
-    let delete_node_body = json!({ "id": node_id });
-    let response = client
-        .post("https://synnq-discovery-f77aaphiwa-uc.a.run.app/delete_node")
-        .json(&delete_node_body)
-        .send().await?;
-
-    if response.status().is_success() {
-        info!("Successfully removed node {} from the discovery service", node_id);
-    } else {
-        eprintln!(
-            "Failed to remove node {} from the discovery service. Status: {}",
-            node_id,
-            response.status()
-        );
-        return Err(
-            anyhow::anyhow!("Failed to remove node {}. Status: {}", node_id, response.status())
-        );
-    }
-
-    Ok(())
-}
@@ -0,0 +1,254 @@
+use crate::decimal::Decimal;
+use crate::network::api::Data;
+use crate::node::node::unix_timestamp;
+use crate::storage::Storage;
+use crate::validation::{ transaction_hash, TransactionData };
+use serde::{ Deserialize, Serialize };
+use std::collections::{ HashMap, VecDeque };
+use thiserror::Error;
+
+const MEMPOOL_PREFIX: &str = "mempool:";
+const TX_STATUS_PREFIX: &str = "tx_status:";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MempoolEntry {
+    pub tx_hash: String,
+    pub fee: Decimal,
+    pub received_at: u64,
+    pub data: Data,
+}
+
+impl MempoolEntry {
+    /// Denom the fee is paid in; fees in different denoms are never compared
+    pub fn denom(&self) -> &str {
+        self.data.data.get("denom").and_then(|denom| denom.as_str()).unwrap_or("")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TxState {
+    Pending,
+    Committed,
+    Rejected,
+    Evicted,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TxStatus {
+    pub tx_hash: String,
+    pub status: TxState,
+    pub detail: Option<String>,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum MempoolError {
+    #[error("Transaction {0} is already pending")]
+    Duplicate(String),
+    #[error("Mempool is full and the fee is too low to evict a pending transaction")]
+    Full,
+}
+
+/// Pending transactions waiting to be proposed, committed by consensus and submitted to the ledger,
+/// persisted in `Storage` so they survive restarts
+pub struct Mempool {
+    storage: Storage,
+    entries: HashMap<String, MempoolEntry>,
+    max_size: usize,
+}
+
+impl Mempool {
+    /// Load the pending transactions persisted by a previous run
+    pub fn load(storage: Storage, max_size: usize) -> Self {
+        let entries = storage
+            .scan_prefix(MEMPOOL_PREFIX)
+            .into_iter()
+            .filter_map(|(_, value)| serde_json::from_str::<MempoolEntry>(&value).ok())
+            .map(|entry| (entry.tx_hash.clone(), entry))
+            .collect();
+
+        Mempool { storage, entries, max_size }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Share of the pool's capacity in use, between 0 and 1
+    pub fn utilization(&self) -> f64 {
+        if self.max_size == 0 { 1.0 } else { (self.entries.len() as f64) / (self.max_size as f64) }
+    }
+
+    /// Queue a validated transaction, evicting the lowest-fee entry in the same denom when the
+    /// pool is full
+    pub fn insert(&mut self, data: Data, fee: Decimal) -> Result<String, MempoolError> {
+        let tx_hash = transaction_hash(&data.data);
+        if self.entries.contains_key(&tx_hash) {
+            return Err(MempoolError::Duplicate(tx_hash));
+        }
+
+        let entry = MempoolEntry {
+            tx_hash: tx_hash.clone(),
+            fee,
+            received_at: unix_timestamp(),
+            data,
+        };
+        if self.entries.len() >= self.max_size {
+            let lowest = self.entries
+                .values()
+                .filter(|pending| pending.denom() == entry.denom())
+                .min_by(|a, b| a.fee.cmp(&b.fee).then(b.received_at.cmp(&a.received_at)))
+                .map(|entry| (entry.tx_hash.clone(), entry.fee));
+            match lowest {
+                Some((lowest_hash, lowest_fee)) if lowest_fee < fee => {
+                    self.remove(&lowest_hash);
+                    self.set_status(
+                        &lowest_hash,
+                        TxState::Evicted,
+                        Some("Evicted by a higher fee transaction".to_string())
+                    );
+                }
+                _ => {
+                    return Err(MempoolError::Full);
+                }
+            }
+        }
+
+        self.storage.store_data(
+            &format!("{}{}", MEMPOOL_PREFIX, tx_hash),
+            &serde_json::to_string(&entry).unwrap()
+        );
+        self.entries.insert(tx_hash.clone(), entry);
+        self.set_status(&tx_hash, TxState::Pending, None);
+
+        Ok(tx_hash)
+    }
+
+    /// The next `size` transactions. Each denom's transactions are taken by descending fee,
+    /// oldest first among equal fees, and the denoms take turns by the age of their next transaction.
+    pub fn next_batch(&self, size: usize) -> Vec<MempoolEntry> {
+        let mut by_denom: HashMap<&str, Vec<&MempoolEntry>> = HashMap::new();
+        for entry in self.entries.values() {
+            by_denom.entry(entry.denom()).or_default().push(entry);
+        }
+        let mut queues: Vec<VecDeque<&MempoolEntry>> = by_denom
+            .into_values()
+            .map(|mut entries| {
+                entries.sort_by(|a, b| b.fee.cmp(&a.fee).then(a.received_at.cmp(&b.received_at)));
+                entries.into()
+            })
+            .collect();
+
+        let mut batch = Vec::new();
+        while batch.len() < size {
+            let next = queues
+                .iter_mut()
+                .filter_map(|queue| queue.front().copied().map(|head| (head, queue)))
+                .min_by(|(a, _), (b, _)| a.received_at.cmp(&b.received_at).then(a.denom().cmp(b.denom())));
+            match next {
+                Some((_, queue)) => batch.extend(queue.pop_front().cloned()),
+                None => break,
+            }
+        }
+        batch
+    }
+
+    pub fn pending(&self) -> Vec<MempoolEntry> {
+        self.next_batch(self.entries.len())
+    }
+
+    /// Pending transactions sent by `sender`
+    pub fn pending_from(&self, sender: &str) -> Vec<TransactionData> {
+        self.entries
+            .values()
+            .filter_map(|entry| serde_json::from_value::<TransactionData>(entry.data.data.clone()).ok())
+            .filter(|transaction| transaction.sender.as_str() == sender)
+            .collect()
+    }
+
+    pub fn remove(&mut self, tx_hash: &str) {
+        self.entries.remove(tx_hash);
+        self.storage.delete_data(&format!("{}{}", MEMPOOL_PREFIX, tx_hash));
+    }
+
+    /// Remove a pending transaction and return it, if it is in the pool
+    pub fn take(&mut self, tx_hash: &str) -> Option<MempoolEntry> {
+        let entry = self.entries.get(tx_hash).cloned();
+        if entry.is_some() {
+            self.remove(tx_hash);
+        }
+        entry
+    }
+
+    pub fn set_status(&self, tx_hash: &str, status: TxState, detail: Option<String>) {
+        let record = TxStatus {
+            tx_hash: tx_hash.to_string(),
+            status,
+            detail,
+            updated_at: unix_timestamp(),
+        };
+        self.storage.store_data(
+            &format!("{}{}", TX_STATUS_PREFIX, tx_hash),
+            &serde_json::to_string(&record).unwrap()
+        );
+    }
+
+    pub fn status(&self, tx_hash: &str) -> Option<TxStatus> {
+        self.storage
+            .get_data(&format!("{}{}", TX_STATUS_PREFIX, tx_hash))
+            .and_then(|value| serde_json::from_str(&value).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn mempool(max_size: usize) -> (Mempool, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("mempool-test-{}", uuid::Uuid::new_v4()));
+        (Mempool::load(Storage::new(path.to_str().unwrap()), max_size), path)
+    }
+
+    fn data(nonce: u64, denom: &str) -> Data {
+        Data { secret: nonce.to_string(), data: json!({ "nonce": nonce, "denom": denom }) }
+    }
+
+    #[test]
+    fn eviction_only_compares_fees_in_the_same_denom() {
+        let (mut mempool, path) = mempool(2);
+        let cheap = mempool.insert(data(0, "SYNNQ"), Decimal::from_units(1, 0)).unwrap();
+        mempool.insert(data(1, "FOO"), Decimal::from_units(5, 0)).unwrap();
+
+        // A large fee in another denom is no reason to evict a SYNNQ transaction
+        assert!(matches!(mempool.insert(data(2, "BAR"), Decimal::from_units(100, 0)), Err(MempoolError::Full)));
+        mempool.insert(data(3, "SYNNQ"), Decimal::from_units(2, 0)).unwrap();
+        assert_eq!(mempool.status(&cheap).unwrap().status, TxState::Evicted);
+        assert_eq!(mempool.len(), 2);
+
+        drop(mempool);
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn batches_order_fees_within_each_denom() {
+        let (mut mempool, path) = mempool(10);
+        mempool.insert(data(0, "SYNNQ"), Decimal::from_units(1, 0)).unwrap();
+        mempool.insert(data(1, "SYNNQ"), Decimal::from_units(3, 0)).unwrap();
+        mempool.insert(data(2, "FOO"), Decimal::from_units(1000, 0)).unwrap();
+
+        let batch = mempool.next_batch(3);
+        let synnq_fees: Vec<_> = batch
+            .iter()
+            .filter(|entry| entry.denom() == "SYNNQ")
+            .map(|entry| entry.fee)
+            .collect();
+        assert_eq!(synnq_fees, vec![Decimal::from_units(3, 0), Decimal::from_units(1, 0)]);
+        assert_eq!(batch.len(), 3);
+        assert_eq!(mempool.next_batch(1).len(), 1);
+
+        drop(mempool);
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
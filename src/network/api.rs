@@ -1,9 +1,60 @@
-use actix_web::{ web, Responder, post, get, HttpResponse, Error };
+use actix_web::{ web, Responder, post, get, HttpRequest, HttpResponse, Error };
+use actix_web::http::StatusCode;
 
 use serde::{ Deserialize, Serialize };
 use serde_json::{Value,json};
-use crate::{ node::node::{ Node, NodeList }, consensus::handle_validation, storage::Storage };
-use crate::validation::validate_data;
+use crate::{ node::node::NodeList, storage::Storage };
+use crate::mempool::{ Mempool, MempoolError, TxState, TxStatus };
+use crate::certificate::QuorumCertificate;
+use crate::batch::BatchLog;
+use crate::sync::{ export_range, SYNC_PAGE_SIZE };
+use crate::broadcast::{
+    already_received,
+    rejected,
+    store_received,
+    store_rejected,
+    verify_broadcast,
+    BroadcastAck,
+    BroadcastMessage,
+    DeliveryStatus,
+    Outbox,
+};
+use crate::consensus::{
+    forward_submission,
+    handle_consensus_message,
+    send_to_api,
+    verify_forwarded,
+    ConsensusContext,
+    ConsensusEnvelope,
+    FORWARDED_HEADER,
+    FORWARDED_SIGNATURE_HEADER,
+};
+use crate::node::signer::NodeSigner;
+use crate::gossip::Gossip;
+use crate::network::registration::{
+    check_known_key,
+    verify_registration,
+    RegisterNodeRequest,
+    RegistrationError,
+    RegistrationGuard,
+};
+use crate::health::HealthState;
+use crate::reputation::ReputationTracker;
+use crate::staking::StakeRegistry;
+use crate::ledger::LedgerClient;
+use crate::accounts::{ check_transaction, load_account, AccountCache };
+use crate::earnings::{ to_csv, EarningsLedger };
+use crate::fee_policy::FeePolicy;
+use crate::denom::DenomRegistry;
+use crate::address::{ Address, AddressFormat };
+use crate::keymanager::{ generate_key_pair_from_mnemonic, generate_key_pair_with_prefix };
+use crate::keymanager::hd::DerivationPath;
+use crate::config::{ Config, NodeWallet };
+use crate::keymanager::keystore::Keystore;
+use zeroize::Zeroizing;
+use crate::evidence::{ receive_evidence, Evidence, EvidencePool };
+use crate::eviction::{ receive_attestation, EvictionTracker, SuspicionAttestation };
+use crate::validation::{ transaction_hash, validate_data, TransactionData };
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -13,16 +64,110 @@ pub struct Data {
     pub data: Value,
 }
 
+/// Status of a transaction, with the quorum certificate proving it was committed
+#[derive(Serialize, Debug)]
+pub struct TransactionResponse {
+    #[serde(flatten)]
+    pub status: TxStatus,
+    pub certificate: Option<QuorumCertificate>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BatchRangeQuery {
+    pub from: u64,
+    pub limit: Option<u64>,
+}
+
+/// Time range of `/earnings`, in Unix seconds, both ends inclusive, and the validator paid
+#[derive(Deserialize, Debug)]
+pub struct EarningsQuery {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub node_id: Option<String>,
+}
+
+/// Transaction to estimate the fee of; `size` is the byte length of its JSON encoding
+#[derive(Deserialize, Debug)]
+pub struct FeeEstimateQuery {
+    pub transaction_type: String,
+    pub denom: String,
+    pub size: Option<usize>,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
-pub struct RegisterNodeRequest {
-    pub id: String,
+pub struct CreateWalletRequest {
+    /// Address prefix, the network's `address_prefix` by default
+    pub prefix: Option<String>,
+    /// Store the private key in the local keystore encrypted with this passphrase instead of
+    /// returning it
+    pub passphrase: Option<String>,
+    /// SLIP-0010 path of the key below the mnemonic's seed, such as `m/44'/5462350'/0'/0'/0'`
+    pub derivation_path: Option<String>,
+    /// Derive the key at the default path of this account number instead of `derivation_path`
+    pub account: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CreateWalletResponse {
     pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub derivation_path: Option<String>,
     pub public_key: String,
+    /// Left out when the node only stores wallet keys encrypted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mnemonic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<String>,
+    /// Whether the private key was stored encrypted in the keystore
+    pub stored: bool,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
-pub struct CreateWalletRequest {
-    pub prefix: String,
+#[derive(Deserialize, Debug)]
+pub struct RecoverWalletRequest {
+    pub mnemonic: String,
+    /// BIP39 passphrase the wallet's seed was derived with, empty if none
+    #[serde(default)]
+    pub bip39_passphrase: String,
+    /// Address prefix, the network's `address_prefix` by default
+    pub prefix: Option<String>,
+    /// Store the private key in the local keystore encrypted with this passphrase instead of
+    /// returning it
+    pub passphrase: Option<String>,
+    /// Make the recovered address the node's `wallet_address`
+    #[serde(default)]
+    pub set_wallet_address: bool,
+    /// SLIP-0010 path of the key below the mnemonic's seed; without a path or account the
+    /// key is the seed's first 32 bytes, as for wallets created before HD derivation
+    pub derivation_path: Option<String>,
+    /// Derive the key at the default path of this account number instead of `derivation_path`
+    pub account: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RecoverWalletResponse {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub derivation_path: Option<String>,
+    pub public_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<String>,
+    /// Whether the private key was stored encrypted in the keystore
+    pub stored: bool,
+    /// Whether the address is now the node's `wallet_address`
+    pub wallet_address_set: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExportWalletRequest {
+    pub address: String,
+    /// Passphrase the private key was stored with
+    pub passphrase: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportWalletResponse {
+    pub address: String,
+    pub private_key: String,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -33,20 +178,61 @@ pub struct ProxyRequest {
 
 #[post("/register_node")]
 async fn register_node(
+    http_req: HttpRequest,
     req: web::Json<RegisterNodeRequest>,
-    node_list: web::Data<Arc<Mutex<NodeList>>>
+    node_list: web::Data<Arc<Mutex<NodeList>>>,
+    guard: web::Data<Arc<Mutex<RegistrationGuard>>>,
+    stakes: web::Data<Arc<Mutex<StakeRegistry>>>,
+    ledger: web::Data<LedgerClient>,
+    address_format: web::Data<Arc<AddressFormat>>
 ) -> impl Responder {
-    let node_list = node_list.lock().await;
+    let admission = {
+        let mut guard = guard.lock().await;
+        match http_req.peer_addr() {
+            Some(peer) => guard.check_rate(peer.ip()),
+            None => Ok(()),
+        }.and_then(|_| guard.check_allowed(&req.id))
+    };
+    let admission = match admission {
+        Ok(()) => check_known_key(&*node_list.lock().await, &req),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = admission {
+        eprintln!("Rejected registration of node {}: {}", req.id, e);
+        return HttpResponse::build(e.status_code()).body(e.to_string());
+    }
 
-    let node = Node {
-        id: req.id.clone(),
-        address: req.address.clone(),
-        public_key: req.public_key.clone(),
-        validated: Some(false),
+    let client = reqwest::Client::new();
+    let min_stake = stakes.lock().await.min_stake();
+    let (node, bonded) = match verify_registration(&req, &client, &ledger, &address_format, min_stake).await {
+        Ok(registered) => registered,
+        Err(e) => {
+            eprintln!("Rejected registration of node {}: {}", req.id, e);
+            return HttpResponse::build(e.status_code()).body(e.to_string());
+        }
     };
 
-    node_list.add_node(node);
-    format!("Node {} registered successfully", req.id)
+    // Checked again under the lock, in case the id was registered while this request was verified
+    let node_list = node_list.lock().await;
+    if let Err(e) = check_known_key(&node_list, &req) {
+        eprintln!("Rejected registration of node {}: {}", req.id, e);
+        return HttpResponse::build(e.status_code()).body(e.to_string());
+    }
+    let mut stakes = stakes.lock().await;
+    if let Some(other) = stakes.backed_node(&req.wallet_address, &req.id) {
+        let e = RegistrationError::WalletInUse {
+            wallet_address: req.wallet_address.clone(),
+            node_id: other.to_string(),
+        };
+        eprintln!("Rejected registration of node {}: {}", req.id, e);
+        return HttpResponse::build(e.status_code()).body(e.to_string());
+    }
+    if let Err(e) = stakes.record_bond(&req.id, &req.wallet_address, bonded) {
+        eprintln!("Failed to record the bond of node {}: {}", req.id, e);
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+    node_list.add_registered_node(node);
+    HttpResponse::Ok().body(format!("Node {} registered successfully", req.id))
 }
 
 #[get("/nodes")]
@@ -56,85 +242,666 @@ async fn get_nodes(node_list: web::Data<Arc<Mutex<NodeList>>>) -> impl Responder
     web::Json(nodes)
 }
 
+#[get("/health")]
+async fn health(health: web::Data<Arc<HealthState>>) -> impl Responder {
+    web::Json(health.report())
+}
+
+#[post("/suspicions")]
+async fn report_suspicion(
+    attestation: web::Json<SuspicionAttestation>,
+    node_list: web::Data<Arc<Mutex<NodeList>>>,
+    tracker: web::Data<Arc<Mutex<EvictionTracker>>>
+) -> impl Responder {
+    let client = reqwest::Client::new();
+    match receive_attestation(attestation.into_inner(), &tracker, &node_list, &client).await {
+        Ok(()) => HttpResponse::Ok().body("Suspicion recorded"),
+        Err(e) => HttpResponse::BadRequest().body(e.to_string()),
+    }
+}
+
+#[get("/suspicions")]
+async fn get_suspicions(tracker: web::Data<Arc<Mutex<EvictionTracker>>>) -> impl Responder {
+    let suspicions = tracker.lock().await.all();
+    web::Json(suspicions)
+}
+
+#[post("/evidence")]
+async fn report_evidence(
+    evidence: web::Json<Evidence>,
+    pool: web::Data<Arc<Mutex<EvidencePool>>>,
+    node_list: web::Data<Arc<Mutex<NodeList>>>,
+    batch_log: web::Data<Arc<Mutex<BatchLog>>>,
+    address_format: web::Data<Arc<AddressFormat>>,
+    signer: web::Data<Arc<NodeSigner>>
+) -> impl Responder {
+    let client = reqwest::Client::new();
+    let result = receive_evidence(
+        evidence.into_inner(),
+        &pool,
+        &node_list,
+        &batch_log,
+        &address_format,
+        &client,
+        signer.node_id()
+    ).await;
+    match result {
+        Ok(()) => HttpResponse::Ok().body("Evidence recorded"),
+        Err(e) => HttpResponse::UnprocessableEntity().body(e.to_string()),
+    }
+}
+
+#[get("/evidence")]
+async fn get_evidence(pool: web::Data<Arc<Mutex<EvidencePool>>>) -> impl Responder {
+    let evidence: Vec<Evidence> = pool
+        .lock().await
+        .all()
+        .iter()
+        .map(Evidence::redacted)
+        .collect();
+    web::Json(evidence)
+}
+
+#[get("/epochs")]
+async fn get_epochs(batch_log: web::Data<Arc<Mutex<BatchLog>>>) -> impl Responder {
+    let validator_sets = batch_log.lock().await.validator_sets().to_vec();
+    web::Json(validator_sets)
+}
+
+#[get("/epochs/{epoch}")]
+async fn get_epoch(epoch: web::Path<u64>, batch_log: web::Data<Arc<Mutex<BatchLog>>>) -> impl Responder {
+    match batch_log.lock().await.validator_set_for_epoch(*epoch) {
+        Some(validators) => HttpResponse::Ok().json(validators),
+        None => HttpResponse::NotFound().body(format!("Epoch {} not found", epoch)),
+    }
+}
+
+#[get("/reputation")]
+async fn get_reputation(reputation: web::Data<Arc<Mutex<ReputationTracker>>>) -> impl Responder {
+    let reports = reputation.lock().await.all();
+    web::Json(reports)
+}
+
+#[get("/reputation/{node_id}")]
+async fn get_node_reputation(
+    node_id: web::Path<String>,
+    reputation: web::Data<Arc<Mutex<ReputationTracker>>>
+) -> impl Responder {
+    match reputation.lock().await.report(&node_id) {
+        Some(report) => HttpResponse::Ok().json(report),
+        None => HttpResponse::NotFound().body(format!("No reputation recorded for node {}", node_id)),
+    }
+}
+
+#[get("/stakes")]
+async fn get_stakes(stakes: web::Data<Arc<Mutex<StakeRegistry>>>) -> impl Responder {
+    let bonds = stakes.lock().await.all();
+    web::Json(bonds)
+}
+
+#[get("/stakes/{node_id}")]
+async fn get_node_stake(
+    node_id: web::Path<String>,
+    stakes: web::Data<Arc<Mutex<StakeRegistry>>>
+) -> impl Responder {
+    match stakes.lock().await.get(&node_id) {
+        Some(bond) => HttpResponse::Ok().json(bond),
+        None => HttpResponse::NotFound().body(format!("No bond recorded for node {}", node_id)),
+    }
+}
+
+#[get("/fee_estimate")]
+async fn get_fee_estimate(
+    query: web::Query<FeeEstimateQuery>,
+    mempool: web::Data<Arc<Mutex<Mempool>>>,
+    fee_policy: web::Data<Arc<FeePolicy>>,
+    denoms: web::Data<Arc<DenomRegistry>>
+) -> impl Responder {
+    if let Err(e) = denoms.get(&query.denom) {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_denom", "message": e.to_string() }));
+    }
+    let utilization = mempool.lock().await.utilization();
+    HttpResponse::Ok().json(
+        fee_policy.estimate(&query.transaction_type, &query.denom, query.size.unwrap_or(0), utilization)
+    )
+}
+
+#[get("/denoms")]
+async fn get_denoms(denoms: web::Data<Arc<DenomRegistry>>) -> impl Responder {
+    web::Json(denoms.all().to_vec())
+}
+
+#[get("/earnings")]
+async fn get_earnings(
+    query: web::Query<EarningsQuery>,
+    earnings: web::Data<Arc<Mutex<EarningsLedger>>>
+) -> impl Responder {
+    let report = earnings.lock().await.report(query.from, query.to, query.node_id.as_deref());
+    web::Json(report)
+}
+
+#[get("/earnings/export")]
+async fn export_earnings(
+    query: web::Query<EarningsQuery>,
+    earnings: web::Data<Arc<Mutex<EarningsLedger>>>
+) -> impl Responder {
+    let records = earnings.lock().await.range(query.from, query.to, query.node_id.as_deref());
+    HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(("Content-Disposition", "attachment; filename=\"earnings.csv\""))
+        .body(to_csv(&records))
+}
+
 #[post("/receive_data")]
 async fn receive_data(
+    req: HttpRequest,
     data: web::Json<Data>,
-    node_list: web::Data<Arc<Mutex<NodeList>>>,
-    storage: web::Data<Arc<Mutex<Storage>>>
+    mempool: web::Data<Arc<Mutex<Mempool>>>,
+    batch_log: web::Data<Arc<Mutex<BatchLog>>>,
+    fee_policy: web::Data<Arc<FeePolicy>>,
+    denoms: web::Data<Arc<DenomRegistry>>,
+    address_format: web::Data<Arc<AddressFormat>>,
+    accounts: web::Data<Arc<Mutex<AccountCache>>>,
+    consensus: web::Data<ConsensusContext>
 ) -> Result<HttpResponse, Error> {
-    // Avoid holding the lock across async boundaries
-    let nodes = {
-        let node_list = node_list.lock().await;
-        node_list.get_nodes().clone()
+    let transaction = match serde_json::from_value::<TransactionData>(data.data.clone()) {
+        Ok(transaction) => transaction,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().body(format!("Invalid transaction: {}", e)));
+        }
     };
-
-    // Validate the data using the first node
-    if !validate_data(&nodes[0], &data.data).await {
+    // Checked before the other fields so that a wrong address gets its own error
+    let addresses = address_format
+        .check(&transaction.sender)
+        .and_then(|_| address_format.check(&transaction.receiver));
+    if let Err(e) = addresses {
+        return Ok(
+            HttpResponse::BadRequest().json(json!({ "error": "invalid_address", "message": e.to_string() }))
+        );
+    }
+    if !validate_data(&data.data, &address_format).await {
         return Ok(HttpResponse::BadRequest().body("Invalid data structure in `data` field"));
     }
+    let amounts = denoms
+        .normalize(&transaction.denom, "amount", &transaction.amount)
+        .and_then(|_| denoms.normalize(&transaction.denom, "fee", &transaction.fee));
+    if let Err(e) = amounts {
+        return Ok(
+            HttpResponse::BadRequest().json(json!({ "error": "invalid_denom", "message": e.to_string() }))
+        );
+    }
+
+    let utilization = mempool.lock().await.utilization();
+    let size = data.data.to_string().len();
+    if
+        let Err(e) = fee_policy.check(
+            &transaction.transaction_type,
+            &transaction.denom,
+            size,
+            transaction.fee,
+            utilization
+        )
+    {
+        return Ok(HttpResponse::build(e.status_code()).json(e.to_json()));
+    }
+
+    let tx_hash = transaction_hash(&data.data);
+    if batch_log.lock().await.get_transaction(&tx_hash).is_some() {
+        return Ok(HttpResponse::Conflict().body(format!("Transaction {} is already committed", tx_hash)));
+    }
+
+    // Submissions go to the sender's home validator, so a transaction is proposed and submitted
+    // to the ledger by a single node and checked against the sender's pending transactions
+    // whichever validator leads; the current leader takes them while the home is unreachable.
+    // Submissions forwarded by another node must be signed by it, otherwise anyone could keep
+    // a transaction away from its home.
+    let header = |name: &str| req.headers().get(name).and_then(|value| value.to_str().ok());
+    if let Some(forwarded_by) = header(FORWARDED_HEADER) {
+        let signature = header(FORWARDED_SIGNATURE_HEADER).unwrap_or_default();
+        let node_list = consensus.node_list.lock().await;
+        if let Err(e) = verify_forwarded(&node_list, forwarded_by, &tx_hash, signature) {
+            return Ok(HttpResponse::Unauthorized().body(e.to_string()));
+        }
+    } else {
+        for target in consensus.forward_targets(transaction.sender.as_str()).await {
+            match forward_submission(&target, &consensus.signer, &data).await {
+                Ok((status, body)) => {
+                    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY);
+                    return Ok(HttpResponse::build(status).content_type("application/json").body(body));
+                }
+                Err(e) => eprintln!("Failed to forward transaction to node {}: {}", target.id, e),
+            }
+        }
+    }
+
+    // Proofs are verified once, here, so that proposing a batch never waits on the ZKP service
+    if !send_to_api(data.0.clone()).await {
+        return Ok(
+            HttpResponse::UnprocessableEntity().json(
+                json!({ "error": "invalid_proof", "message": "Data validation failed on external API" })
+            )
+        );
+    }
+
+    // Reject replays and transactions the sender cannot afford before they reach consensus;
+    // the mempool stays locked so that concurrent submissions see each other
+    let account = match load_account(&accounts, transaction.sender.as_str()).await {
+        Ok(account) => account,
+        Err(e) => {
+            return Ok(HttpResponse::build(e.status_code()).json(e.to_json()));
+        }
+    };
+    let mut mempool = mempool.lock().await;
+    let checked = accounts
+        .lock().await
+        .check_not_paying_fees(transaction.sender.as_str())
+        .and_then(|_| check_transaction(&account, &mempool.pending_from(transaction.sender.as_str()), &transaction));
+    if let Err(e) = checked {
+        return Ok(HttpResponse::build(e.status_code()).json(e.to_json()));
+    }
+
+    // Queue the transaction until this node proposes it to consensus
+    let result = mempool.insert(data.into_inner(), transaction.fee);
+    match result {
+        Ok(tx_hash) =>
+            Ok(HttpResponse::Accepted().json(json!({ "tx_hash": tx_hash, "status": "pending" }))),
+        Err(e @ MempoolError::Duplicate(_)) => Ok(HttpResponse::Conflict().body(e.to_string())),
+        Err(e @ MempoolError::Full) => Ok(HttpResponse::ServiceUnavailable().body(e.to_string())),
+    }
+}
+
+#[get("/mempool")]
+async fn get_mempool(mempool: web::Data<Arc<Mutex<Mempool>>>) -> impl Responder {
+    let mempool = mempool.lock().await;
+    let pending: Vec<_> = mempool
+        .pending()
+        .into_iter()
+        .map(|entry| {
+            json!({ "tx_hash": entry.tx_hash, "fee": entry.fee, "received_at": entry.received_at })
+        })
+        .collect();
+    web::Json(json!({ "size": mempool.len(), "pending": pending }))
+}
+
+#[get("/transactions/{tx_hash}")]
+async fn get_transaction_status(
+    tx_hash: web::Path<String>,
+    mempool: web::Data<Arc<Mutex<Mempool>>>,
+    batch_log: web::Data<Arc<Mutex<BatchLog>>>
+) -> impl Responder {
+    let certificate = QuorumCertificate::for_transaction(&*batch_log.lock().await, &tx_hash);
+    let status = mempool.lock().await.status(&tx_hash);
+
+    // Transactions received from other nodes only appear in the batch log
+    let status = match (status, &certificate) {
+        (Some(status), _) => status,
+        (None, Some(certificate)) =>
+            TxStatus {
+                tx_hash: tx_hash.to_string(),
+                status: TxState::Committed,
+                detail: Some(format!("Committed in batch {}", certificate.header.height)),
+                updated_at: certificate.header.timestamp,
+            },
+        (None, None) => {
+            return HttpResponse::NotFound().body(format!("Transaction {} not found", tx_hash));
+        }
+    };
+    HttpResponse::Ok().json(TransactionResponse { status, certificate })
+}
+
+#[get("/batches")]
+async fn get_batches(
+    query: web::Query<BatchRangeQuery>,
+    batch_log: web::Data<Arc<Mutex<BatchLog>>>
+) -> impl Responder {
+    let batch_log = batch_log.lock().await;
+    web::Json(export_range(&batch_log, query.from, query.limit.unwrap_or(SYNC_PAGE_SIZE)))
+}
+
+#[get("/batches/latest")]
+async fn get_latest_batch(batch_log: web::Data<Arc<Mutex<BatchLog>>>) -> impl Responder {
+    let batch_log = batch_log.lock().await;
+    match batch_log.get(batch_log.height()) {
+        Some(batch) => HttpResponse::Ok().json(batch),
+        None => HttpResponse::NotFound().body("No batches produced yet"),
+    }
+}
+
+#[get("/batches/{height}")]
+async fn get_batch(
+    height: web::Path<u64>,
+    batch_log: web::Data<Arc<Mutex<BatchLog>>>
+) -> impl Responder {
+    match batch_log.lock().await.get(*height) {
+        Some(batch) => HttpResponse::Ok().json(batch),
+        None => HttpResponse::NotFound().body(format!("Batch {} not found", height)),
+    }
+}
 
-    // Perform validation and broadcast
-    handle_validation(data.into_inner(), node_list.clone(), storage.clone()).await
+#[post("/consensus")]
+async fn receive_consensus(
+    envelope: web::Json<ConsensusEnvelope>,
+    consensus: web::Data<ConsensusContext>
+) -> impl Responder {
+    match handle_consensus_message(&consensus, envelope.into_inner()).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => HttpResponse::Unauthorized().body(e.to_string()),
+    }
+}
+
+#[get("/consensus")]
+async fn get_consensus(consensus: web::Data<ConsensusContext>) -> impl Responder {
+    let status = consensus.state.lock().await.status();
+    HttpResponse::Ok().json(status)
 }
 
 #[post("/receive_broadcast")]
 async fn receive_broadcast(
-    transaction_data: web::Json<Value>,
-    storage: web::Data<Arc<Mutex<Storage>>>
+    message: web::Json<BroadcastMessage>,
+    node_list: web::Data<Arc<Mutex<NodeList>>>,
+    storage: web::Data<Arc<Mutex<Storage>>>,
+    outbox: web::Data<Arc<Mutex<Outbox>>>,
+    gossip: web::Data<Arc<Mutex<Gossip>>>,
+    signer: web::Data<Arc<NodeSigner>>,
+    reputation: web::Data<Arc<Mutex<ReputationTracker>>>,
+    batch_log: web::Data<Arc<Mutex<BatchLog>>>,
+    address_format: web::Data<Arc<AddressFormat>>
 ) -> impl Responder {
-    println!("Received broadcasted transaction data: {:?}", transaction_data);
+    println!(
+        "Received broadcast {} from node {} (origin {}, ttl {})",
+        message.message_id,
+        message.relayed_by,
+        message.origin_id,
+        message.ttl
+    );
 
-    let storage_key = "broadcasted_transaction";
+    // Redelivered and looping messages are acknowledged again without being stored or relayed
+    let seen = gossip.lock().await.has_seen(&message.message_id) ||
+        already_received(&*storage.lock().await, &message.message_id);
 
-    {
-        let storage = storage.lock().await;
-        storage.store_data(storage_key, &transaction_data.to_string());
+    if !seen {
+        let validators = batch_log
+            .lock().await
+            .validator_set_for_epoch(message.certificate.header.epoch)
+            .cloned();
+        let validators = match validators {
+            Some(validators) => validators,
+            None => {
+                // The sender retries once this node has synced the epoch
+                return HttpResponse::ServiceUnavailable().body(
+                    format!("Validator set of epoch {} is not known yet", message.certificate.header.epoch)
+                );
+            }
+        };
+
+        if let Err(e) = verify_broadcast(&message, &node_list, &validators, &address_format).await {
+            eprintln!(
+                "Rejected broadcast {} from node {}: {}",
+                message.message_id,
+                message.relayed_by,
+                e
+            );
+            store_rejected(&*storage.lock().await, &message, &e.to_string());
+            return HttpResponse::UnprocessableEntity().body(e.to_string());
+        }
+
+        let is_new = gossip.lock().await.mark_seen(&message.message_id);
+        if is_new {
+            store_received(&*storage.lock().await, &message);
+
+            let peers = {
+                let node_list = node_list.lock().await;
+                node_list.get_nodes()
+            };
+            let gossip = gossip.lock().await;
+            match gossip.relay(&message, &signer) {
+                Ok(Some(relayed)) => {
+                    let targets = gossip.select_peers(
+                        &peers,
+                        &message,
+                        signer.node_id(),
+                        &*reputation.lock().await
+                    );
+                    outbox.lock().await.enqueue(&relayed, &targets);
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Failed to relay broadcast {}: {}", message.message_id, e),
+            }
+        }
+    }
+
+    match BroadcastAck::new(&signer, &message.message_id) {
+        Ok(ack) => HttpResponse::Ok().json(ack),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
+}
+
+#[get("/broadcasts/rejected")]
+async fn get_rejected_broadcasts(storage: web::Data<Arc<Mutex<Storage>>>) -> impl Responder {
+    let rejected = rejected(&*storage.lock().await);
+    web::Json(rejected)
+}
+
+#[get("/broadcasts/pending")]
+async fn get_pending_broadcasts(outbox: web::Data<Arc<Mutex<Outbox>>>) -> impl Responder {
+    // Only hashes and delivery state: the queued transactions carry their private keys
+    let pending: Vec<DeliveryStatus> = outbox
+        .lock().await
+        .pending()
+        .into_iter()
+        .map(DeliveryStatus::from)
+        .collect();
+    web::Json(pending)
+}
 
-    HttpResponse::Ok().body("Broadcast received successfully")
+/// Whether a request comes from the node's own host, the only one allowed to manage wallets
+fn is_local_admin(req: &HttpRequest) -> bool {
+    req.peer_addr().map_or(false, |peer| peer.ip().is_loopback())
 }
 
+/// Generate a wallet with the local key manager. Only served to local callers, since the
+/// response carries, unless keys are only stored encrypted, the mnemonic and, unless a
+/// passphrase is given, the private key.
 #[post("/create_wallet")]
 async fn create_wallet(
+    http_req: HttpRequest,
     req: web::Json<CreateWalletRequest>,
-) -> Result<HttpResponse, Error> {
-    let client = reqwest::Client::builder()
-        .build()
-        .map_err(|e| {
-            // Manually map the reqwest error to an actix error
-            actix_web::error::ErrorInternalServerError(format!("Client build error: {:?}", e))
-        })?;
-
-    let json = json!({
-        "prefix": req.prefix
+    address_format: web::Data<Arc<AddressFormat>>,
+    keystore: web::Data<Arc<Keystore>>
+) -> impl Responder {
+    if !is_local_admin(&http_req) {
+        return HttpResponse::Forbidden().body("Wallets can only be created from the node's host");
+    }
+    if keystore.encrypted_only() && req.passphrase.is_none() {
+        return HttpResponse::BadRequest().body(
+            "A passphrase is required: this node only stores wallet keys encrypted"
+        );
+    }
+
+    let path = match DerivationPath::select(req.derivation_path.as_deref(), req.account) {
+        Ok(path) => path,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(json!({ "error": "invalid_path", "message": e.to_string() }));
+        }
+    };
+    let prefix = req.prefix.clone().unwrap_or_else(|| address_format.prefix().to_string());
+    let (public_key, private_key, mnemonic, address) = generate_key_pair_with_prefix(&prefix, path.as_ref());
+    let private_key = Zeroizing::new(private_key);
+    if let Err(e) = Address::parse_with_prefix(&address, &prefix) {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+
+    let private_key = match &req.passphrase {
+        Some(passphrase) => {
+            if let Err(e) = keystore.store(&address, &public_key, &private_key, passphrase) {
+                eprintln!("Failed to store the key of wallet {}: {}", address, e);
+                return HttpResponse::InternalServerError().body(e.to_string());
+            }
+            None
+        }
+        None => Some(private_key.to_string()),
+    };
+    // The mnemonic derives the private key, so it is only returned where the key could be
+    let mnemonic = if keystore.encrypted_only() { None } else { Some(mnemonic) };
+    println!("Created wallet {}", address);
+    HttpResponse::Ok().json(CreateWalletResponse {
+        stored: private_key.is_none(),
+        derivation_path: path.map(|path| path.to_string()),
+        address,
+        public_key,
+        mnemonic,
+        private_key,
+    })
+}
+
+/// Derive a wallet's keys from its mnemonic with the local key manager, optionally storing the
+/// key encrypted and making the wallet the node's `wallet_address`. Only served to local callers.
+#[post("/wallet/recover")]
+async fn recover_wallet(
+    http_req: HttpRequest,
+    req: web::Json<RecoverWalletRequest>,
+    address_format: web::Data<Arc<AddressFormat>>,
+    keystore: web::Data<Arc<Keystore>>,
+    node_wallet: web::Data<Arc<Mutex<NodeWallet>>>
+) -> impl Responder {
+    if !is_local_admin(&http_req) {
+        return HttpResponse::Forbidden().body("Wallets can only be recovered from the node's host");
+    }
+    if keystore.encrypted_only() && req.passphrase.is_none() {
+        return HttpResponse::BadRequest().body(
+            "A passphrase is required: this node only stores wallet keys encrypted"
+        );
+    }
+
+    let path = match DerivationPath::select(req.derivation_path.as_deref(), req.account) {
+        Ok(path) => path,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(json!({ "error": "invalid_path", "message": e.to_string() }));
+        }
+    };
+    let prefix = req.prefix.clone().unwrap_or_else(|| address_format.prefix().to_string());
+    let (public_key, private_key, address) = match
+        generate_key_pair_from_mnemonic(&req.mnemonic, &req.bip39_passphrase, &prefix, path.as_ref())
+    {
+        Ok(keys) => keys,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(
+                json!({ "error": "invalid_mnemonic", "message": e.to_string() })
+            );
+        }
+    };
+    let private_key = Zeroizing::new(private_key);
+    let parsed = Address::parse_with_prefix(&address, &prefix).and_then(|address| {
+        if req.set_wallet_address { address_format.check(&address) } else { Ok(()) }
     });
+    if let Err(e) = parsed {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_address", "message": e.to_string() }));
+    }
 
-    let res = client
-        .post("https://rest.synnq.io/create_wallet")
-        .json(&json)
-        .send()
-        .await
-        .map_err(|e| {
-            // Manually map the reqwest error to an actix error
-            actix_web::error::ErrorInternalServerError(format!("Request error: {:?}", e))
-        })?;
+    let private_key = match &req.passphrase {
+        Some(passphrase) => {
+            if let Err(e) = keystore.store(&address, &public_key, &private_key, passphrase) {
+                eprintln!("Failed to store the key of wallet {}: {}", address, e);
+                return HttpResponse::InternalServerError().body(e.to_string());
+            }
+            None
+        }
+        None => Some(private_key.to_string()),
+    };
 
-    let body = res
-        .text()
-        .await
-        .map_err(|e| {
-            // Manually map the reqwest error to an actix error
-            actix_web::error::ErrorInternalServerError(format!("Response error: {:?}", e))
-        })?;
+    if req.set_wallet_address {
+        let mut wallet = node_wallet.lock().await;
+        let config_file = wallet.config_file.clone();
+        let wallet_address = address.clone();
+        let saved = web::block(move || {
+            let mut config = Config::read(&config_file)?;
+            config.wallet_address = Some(wallet_address);
+            config.save(&config_file)
+        }).await
+            .map_err(|e| e.to_string())
+            .and_then(|saved| saved.map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            eprintln!("Failed to set wallet {} as the node's wallet_address: {}", address, e);
+            return HttpResponse::InternalServerError().body(e);
+        }
+        wallet.address = address.clone();
+        println!("Set recovered wallet {} as the node's wallet_address", address);
+    } else {
+        println!("Recovered wallet {}", address);
+    }
+    HttpResponse::Ok().json(RecoverWalletResponse {
+        stored: private_key.is_none(),
+        wallet_address_set: req.set_wallet_address,
+        derivation_path: path.map(|path| path.to_string()),
+        address,
+        public_key,
+        private_key,
+    })
+}
 
-    Ok(HttpResponse::Ok().body(body))
+#[get("/wallets")]
+async fn get_wallets(http_req: HttpRequest, keystore: web::Data<Arc<Keystore>>) -> impl Responder {
+    if !is_local_admin(&http_req) {
+        return HttpResponse::Forbidden().body("Wallets can only be listed from the node's host");
+    }
+    HttpResponse::Ok().json(keystore.all())
 }
 
+/// Decrypt a stored wallet key with its passphrase. Only served to local callers.
+#[post("/wallet/export")]
+async fn export_wallet(
+    http_req: HttpRequest,
+    req: web::Json<ExportWalletRequest>,
+    keystore: web::Data<Arc<Keystore>>
+) -> impl Responder {
+    if !is_local_admin(&http_req) {
+        return HttpResponse::Forbidden().body("Wallets can only be exported from the node's host");
+    }
+    let private_key = match keystore.load(&req.address, &req.passphrase) {
+        Ok(private_key) => private_key,
+        Err(e) => {
+            eprintln!("Failed to export wallet {}: {}", req.address, e);
+            return HttpResponse::build(e.status_code()).body(e.to_string());
+        }
+    };
+    println!("Exported the key of wallet {}", req.address);
+    HttpResponse::Ok().json(ExportWalletResponse {
+        address: req.address.clone(),
+        private_key: private_key.to_string(),
+    })
+}
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(register_node);
     cfg.service(get_nodes);
+    cfg.service(health);
+    cfg.service(report_suspicion);
+    cfg.service(get_suspicions);
+    cfg.service(report_evidence);
+    cfg.service(get_evidence);
+    cfg.service(get_epochs);
+    cfg.service(get_epoch);
+    cfg.service(get_reputation);
+    cfg.service(get_node_reputation);
+    cfg.service(get_stakes);
+    cfg.service(get_node_stake);
+    cfg.service(get_earnings);
+    cfg.service(export_earnings);
     cfg.service(receive_data);
+    cfg.service(get_mempool);
+    cfg.service(get_fee_estimate);
+    cfg.service(get_denoms);
+    cfg.service(get_transaction_status);
+    cfg.service(get_batches);
+    cfg.service(get_latest_batch);
+    cfg.service(get_batch);
     cfg.service(receive_broadcast);
+    cfg.service(get_pending_broadcasts);
+    cfg.service(get_rejected_broadcasts);
+    cfg.service(receive_consensus);
+    cfg.service(get_consensus);
     cfg.service(create_wallet);
+    cfg.service(recover_wallet);
+    cfg.service(get_wallets);
+    cfg.service(export_wallet);
 }
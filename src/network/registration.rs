@@ -0,0 +1,216 @@
+use crate::address::{ AddressError, AddressFormat };
+use crate::config::Config;
+use crate::health::probe_node;
+use crate::node::node::{ unix_timestamp, Node, NodeList, NodeStatus };
+use crate::node::signer::verify_signature;
+use crate::keymanager::verify_wallet_signature;
+use crate::ledger::LedgerClient;
+use actix_web::http::StatusCode;
+use reqwest::Client;
+use serde::{ Deserialize, Serialize };
+use std::collections::{ HashMap, HashSet };
+use std::net::IpAddr;
+use thiserror::Error;
+use tokio::time::{ Duration, Instant };
+
+/// Maximum age of a registration signature before it is considered a replay
+const REGISTRATION_MAX_AGE_SECS: u64 = 300;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RegisterNodeRequest {
+    pub id: String,
+    pub address: String,
+    pub public_key: String,
+    /// Wallet that must have bonded the minimum stake on the ledger
+    pub wallet_address: String,
+    /// Unix timestamp (seconds) included in the signed message
+    pub timestamp: u64,
+    /// Hex signature over `registration_message` made with `public_key`
+    pub signature: String,
+    /// Hex Ed25519 public key of `wallet_address`
+    pub wallet_public_key: String,
+    /// Hex signature over `wallet_message` made with the wallet's key, proving the wallet's
+    /// owner lends its stake to this node
+    pub wallet_signature: String,
+}
+
+#[derive(Debug, Error)]
+pub enum RegistrationError {
+    #[error("Too many registration requests from {0}")]
+    RateLimited(IpAddr),
+    #[error("Node {0} is not in the registration allowlist")]
+    NotAllowed(String),
+    #[error("Registration timestamp is too old or in the future")]
+    Expired,
+    #[error("Invalid registration signature")]
+    InvalidSignature,
+    #[error("Node {0} is already registered with a different public key")]
+    KeyMismatch(String),
+    #[error("Invalid signature by the key of wallet {0}")]
+    InvalidWalletSignature(String),
+    #[error("Wallet {wallet_address} already backs node {node_id}")]
+    WalletInUse {
+        wallet_address: String,
+        node_id: String,
+    },
+    #[error("Node address is unreachable: {0}")]
+    Unreachable(String),
+    #[error("Invalid wallet address: {0}")]
+    InvalidWallet(#[from] AddressError),
+    #[error("Wallet {wallet_address} has bonded {bonded}, below the minimum stake of {required}")]
+    InsufficientStake {
+        wallet_address: String,
+        bonded: u64,
+        required: u64,
+    },
+    #[error("Failed to check the bonded stake on the ledger: {0}")]
+    Ledger(String),
+}
+
+impl RegistrationError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            RegistrationError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            RegistrationError::NotAllowed(_) => StatusCode::FORBIDDEN,
+            RegistrationError::Expired | RegistrationError::InvalidSignature =>
+                StatusCode::UNAUTHORIZED,
+            RegistrationError::Unreachable(_) | RegistrationError::InvalidWallet(_) =>
+                StatusCode::BAD_REQUEST,
+            RegistrationError::KeyMismatch(_) | RegistrationError::WalletInUse { .. } => StatusCode::CONFLICT,
+            RegistrationError::InvalidWalletSignature(_) => StatusCode::UNAUTHORIZED,
+            RegistrationError::InsufficientStake { .. } => StatusCode::FORBIDDEN,
+            RegistrationError::Ledger(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+/// The message a registering node signs to prove possession of its private key
+pub fn registration_message(id: &str, address: &str, wallet_address: &str, timestamp: u64) -> Vec<u8> {
+    format!("register:{}:{}:{}:{}", id, address, wallet_address, timestamp).into_bytes()
+}
+
+/// The message the owner of a node's wallet signs to bond its stake to that node
+pub fn wallet_message(id: &str, timestamp: u64) -> Vec<u8> {
+    format!("bond:{}:{}", id, timestamp).into_bytes()
+}
+
+/// Admission policy for `/register_node`: per-IP rate limiting and an optional allowlist
+pub struct RegistrationGuard {
+    allowlist: Option<HashSet<String>>,
+    limit_per_window: u32,
+    windows: HashMap<IpAddr, (Instant, u32)>,
+}
+
+impl RegistrationGuard {
+    pub fn from_config(config: &Config) -> Self {
+        RegistrationGuard {
+            allowlist: config.registration_allowlist
+                .as_ref()
+                .map(|ids| ids.iter().cloned().collect()),
+            limit_per_window: config.registration_rate_limit,
+            windows: HashMap::new(),
+        }
+    }
+
+    /// Count a request from `ip` against its fixed one-minute window
+    pub fn check_rate(&mut self, ip: IpAddr) -> Result<(), RegistrationError> {
+        let now = Instant::now();
+        let window = self.windows.entry(ip).or_insert((now, 0));
+        if now.duration_since(window.0) >= RATE_LIMIT_WINDOW {
+            *window = (now, 0);
+        }
+
+        if window.1 >= self.limit_per_window {
+            return Err(RegistrationError::RateLimited(ip));
+        }
+        window.1 += 1;
+
+        self.windows.retain(|_, (start, _)| now.duration_since(*start) < RATE_LIMIT_WINDOW);
+        Ok(())
+    }
+
+    pub fn check_allowed(&self, id: &str) -> Result<(), RegistrationError> {
+        match &self.allowlist {
+            Some(allowlist) if !allowlist.contains(id) => {
+                Err(RegistrationError::NotAllowed(id.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A known node id can only be registered again with the public key already on record, so
+/// that nobody can take over a validator's id with their own key
+pub fn check_known_key(node_list: &NodeList, req: &RegisterNodeRequest) -> Result<(), RegistrationError> {
+    if !node_list.is_registered(&req.id) {
+        return Ok(());
+    }
+    match node_list.find_node_by_uuid(&req.id) {
+        Some(existing) if existing.public_key.trim() != req.public_key.trim() => {
+            Err(RegistrationError::KeyMismatch(req.id.clone()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Verify the proof-of-possession signature, that the node's wallet is an address of this network
+/// that bonded at least `min_stake` and that the node answers on its address, returning the node to insert into the `NodeList`
+/// with its bonded stake
+pub async fn verify_registration(
+    req: &RegisterNodeRequest,
+    client: &Client,
+    ledger: &LedgerClient,
+    address_format: &AddressFormat,
+    min_stake: u64
+) -> Result<(Node, u64), RegistrationError> {
+    if unix_timestamp().abs_diff(req.timestamp) > REGISTRATION_MAX_AGE_SECS {
+        return Err(RegistrationError::Expired);
+    }
+
+    let message = registration_message(&req.id, &req.address, &req.wallet_address, req.timestamp);
+    if !verify_signature(&req.public_key, &message, &req.signature) {
+        return Err(RegistrationError::InvalidSignature);
+    }
+    let wallet = address_format.parse(&req.wallet_address)?;
+    let wallet_signed = verify_wallet_signature(
+        &req.wallet_public_key,
+        &wallet_message(&req.id, req.timestamp),
+        &req.wallet_signature
+    );
+    if !address_format.is_owned_by(&wallet, &req.wallet_public_key) || !wallet_signed {
+        return Err(RegistrationError::InvalidWalletSignature(req.wallet_address.clone()));
+    }
+
+    let bonded = ledger
+        .bonded_stake(&req.wallet_address).await
+        .map_err(|e| RegistrationError::Ledger(e.to_string()))?;
+    if bonded < min_stake {
+        return Err(RegistrationError::InsufficientStake {
+            wallet_address: req.wallet_address.clone(),
+            bonded,
+            required: min_stake,
+        });
+    }
+
+    let mut node = Node {
+        id: req.id.clone(),
+        address: req.address.clone(),
+        public_key: req.public_key.clone(),
+        wallet_address: Some(req.wallet_address.clone()),
+        validated: Some(false),
+        last_seen: None,
+        latency_ms: None,
+        consecutive_failures: 0,
+        status: NodeStatus::Active,
+    };
+
+    let (latency, report) = probe_node(&node, client).await.map_err(|e|
+        RegistrationError::Unreachable(e.to_string())
+    )?;
+    node.last_seen = Some(unix_timestamp());
+    node.latency_ms = Some(latency.as_millis() as u64);
+    node.validated = Some(report.ready);
+
+    Ok((node, bonded))
+}
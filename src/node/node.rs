@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
@@ -6,24 +6,53 @@ use rand::rngs::OsRng;
 use rsa::{RsaPrivateKey, RsaPublicKey};
 use rsa::pkcs1::{EncodeRsaPrivateKey, EncodeRsaPublicKey, LineEnding};  // Import the traits
 use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Consecutive failed health checks after which a node is considered down
+pub const DOWN_THRESHOLD: u32 = 3;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeStatus {
+    #[default]
+    Active,
+    Suspect,
+    Down,
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Node {
     pub id: String,
     pub address: String,
     pub public_key: String,
+    /// Wallet whose bonded stake backs the node as a validator
+    #[serde(default)]
+    pub wallet_address: Option<String>,
     pub validated: Option<bool>,
+    /// Unix timestamp (seconds) of the last successful health check
+    #[serde(default)]
+    pub last_seen: Option<u64>,
+    /// Round-trip time of the last successful health check in milliseconds
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    #[serde(default)]
+    pub status: NodeStatus,
 }
 
 #[derive(Clone)]
 pub struct NodeList {
     nodes: Arc<Mutex<HashMap<String, Node>>>,
+    /// Nodes that proved their key and wallet through `/register_node`
+    registered: Arc<Mutex<HashSet<String>>>,
 }
 
 impl NodeList {
     pub fn new() -> Self {
         NodeList {
             nodes: Arc::new(Mutex::new(HashMap::new())),
+            registered: Arc::new(Mutex::new(HashSet::new())),
         }
     }
 
@@ -39,6 +68,17 @@ impl NodeList {
         self.nodes.lock().unwrap().insert(node.id.clone(), node);
     }
 
+    /// Add a node whose key and wallet were verified at registration, which discovery
+    /// refreshes then never replace
+    pub fn add_registered_node(&self, node: Node) {
+        self.registered.lock().unwrap().insert(node.id.clone());
+        self.add_node(node);
+    }
+
+    pub fn is_registered(&self, uuid: &str) -> bool {
+        self.registered.lock().unwrap().contains(uuid)
+    }
+
     pub fn get_nodes(&self) -> Vec<Node> {
         self.nodes.lock().unwrap().values().cloned().collect()
     }
@@ -47,7 +87,60 @@ impl NodeList {
         self.nodes.lock().unwrap().get(uuid).cloned()
     }
 
+    /// Replace the node list with a fresh one from discovery, keeping the
+    /// health metadata of nodes that are already known. Nodes registered
+    /// locally are kept as they are, since discovery does not prove keys.
+    pub fn merge_nodes(&self, nodes: Vec<Node>) {
+        let mut current = self.nodes.lock().unwrap();
+        let registered = self.registered.lock().unwrap();
+        let mut merged: HashMap<String, Node> = current
+            .iter()
+            .filter(|(id, _)| registered.contains(*id))
+            .map(|(id, node)| (id.clone(), node.clone()))
+            .collect();
+        for mut node in nodes {
+            if merged.contains_key(&node.id) {
+                continue;
+            }
+            if let Some(existing) = current.remove(&node.id) {
+                node.wallet_address = node.wallet_address.or(existing.wallet_address);
+                node.validated = existing.validated;
+                node.last_seen = existing.last_seen;
+                node.latency_ms = existing.latency_ms;
+                node.consecutive_failures = existing.consecutive_failures;
+                node.status = existing.status;
+            }
+            merged.insert(node.id.clone(), node);
+        }
+        *current = merged;
+    }
+
+    /// Record a successful health check for a node
+    pub fn record_health_success(&self, uuid: &str, latency_ms: u64, ready: bool) {
+        if let Some(node) = self.nodes.lock().unwrap().get_mut(uuid) {
+            node.last_seen = Some(unix_timestamp());
+            node.latency_ms = Some(latency_ms);
+            node.consecutive_failures = 0;
+            node.status = NodeStatus::Active;
+            node.validated = Some(ready);
+        }
+    }
+
+    /// Record a failed health check for a node and return its new status
+    pub fn record_health_failure(&self, uuid: &str) -> Option<NodeStatus> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = nodes.get_mut(uuid)?;
+        node.consecutive_failures += 1;
+        node.status = if node.consecutive_failures >= DOWN_THRESHOLD {
+            NodeStatus::Down
+        } else {
+            NodeStatus::Suspect
+        };
+        Some(node.status)
+    }
+
     pub fn remove_node_by_uuid(&self, uuid: &str) -> bool {
+        self.registered.lock().unwrap().remove(uuid);
         let mut nodes = self.nodes.lock().unwrap();
         if nodes.remove(uuid).is_some() {
             true
@@ -58,7 +151,7 @@ impl NodeList {
 }
 
 impl Node {
-    pub fn new(address: &str) -> Self {
+    pub fn new(address: &str, wallet_address: Option<String>) -> Self {
         let id = Uuid::new_v4().to_string();
 
         let mut rng = OsRng;
@@ -74,7 +167,73 @@ impl Node {
             id,
             address: address.to_string(),
             public_key: public_key_pem,
+            wallet_address,
             validated: Some(false),
+            last_seen: None,
+            latency_ms: None,
+            consecutive_failures: 0,
+            status: NodeStatus::Active,
         }
     }
-}
\ No newline at end of file
+
+    /// Build the URL of an endpoint on this node, defaulting to http when the
+    /// address has no scheme
+    pub fn url(&self, path: &str) -> String {
+        if self.address.starts_with("http://") || self.address.starts_with("https://") {
+            format!("{}{}", self.address.trim_end_matches('/'), path)
+        } else {
+            format!("http://{}{}", self.address, path)
+        }
+    }
+}
+
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, public_key: &str) -> Node {
+        Node {
+            id: id.to_string(),
+            address: format!("{}.example:8080", id),
+            public_key: public_key.to_string(),
+            wallet_address: Some(format!("synnq_{}", id)),
+            validated: Some(true),
+            last_seen: None,
+            latency_ms: None,
+            consecutive_failures: 0,
+            status: NodeStatus::Active,
+        }
+    }
+
+    #[test]
+    fn merge_keeps_registered_nodes_and_their_keys() {
+        let node_list = NodeList::new();
+        node_list.add_registered_node(node("a", "proven"));
+        node_list.add_node(node("b", "from-discovery"));
+
+        let mut forged = node("a", "forged");
+        forged.wallet_address = Some("synnq_attacker".to_string());
+        node_list.merge_nodes(vec![forged, node("c", "from-discovery")]);
+
+        let a = node_list.find_node_by_uuid("a").unwrap();
+        assert_eq!(a.public_key, "proven");
+        assert_eq!(a.wallet_address.as_deref(), Some("synnq_a"));
+        assert!(node_list.find_node_by_uuid("b").is_none());
+        assert!(node_list.find_node_by_uuid("c").is_some());
+
+        node_list.merge_nodes(Vec::new());
+        assert!(node_list.find_node_by_uuid("a").is_some());
+    }
+
+    #[test]
+    fn removed_nodes_are_no_longer_registered() {
+        let node_list = NodeList::new();
+        node_list.add_registered_node(node("a", "proven"));
+        assert!(node_list.remove_node_by_uuid("a"));
+        assert!(!node_list.is_registered("a"));
+    }
+}
@@ -0,0 +1,58 @@
+use anyhow::{ anyhow, Result };
+use rsa::pkcs1::{ DecodeRsaPrivateKey, DecodeRsaPublicKey };
+use rsa::{ Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey };
+use sha2::{ Digest, Sha256 };
+use std::fs;
+
+/// Signs messages on behalf of the local node with the RSA key written by `Node::new`
+pub struct NodeSigner {
+    node_id: String,
+    private_key: RsaPrivateKey,
+}
+
+impl NodeSigner {
+    /// Load the node's PKCS#1 PEM private key from disk
+    pub fn load(path: &str, node_id: &str) -> Result<Self> {
+        let pem = fs::read_to_string(path)?;
+        let private_key = RsaPrivateKey::from_pkcs1_pem(&pem).map_err(|e|
+            anyhow!("Invalid private key in {}: {}", path, e)
+        )?;
+
+        Ok(NodeSigner {
+            node_id: node_id.to_string(),
+            private_key,
+        })
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// Sign a message with RSA PKCS#1 v1.5 over SHA-256 and return the hex signature
+    pub fn sign(&self, message: &[u8]) -> Result<String> {
+        let digest = Sha256::digest(message);
+        let signature = self.private_key
+            .sign(Pkcs1v15Sign::new::<Sha256>(), &digest)
+            .map_err(|e| anyhow!("Failed to sign message: {}", e))?;
+        Ok(hex::encode(signature))
+    }
+}
+
+/// Verify a hex signature produced by `NodeSigner::sign` against a node's PEM public key
+pub fn verify_signature(public_key_pem: &str, message: &[u8], signature: &str) -> bool {
+    let public_key = match RsaPublicKey::from_pkcs1_pem(public_key_pem) {
+        Ok(key) => key,
+        Err(_) => {
+            return false;
+        }
+    };
+    let signature = match hex::decode(signature) {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return false;
+        }
+    };
+
+    let digest = Sha256::digest(message);
+    public_key.verify(Pkcs1v15Sign::new::<Sha256>(), &digest, &signature).is_ok()
+}
@@ -0,0 +1,211 @@
+use crate::node::node::unix_timestamp;
+use crate::storage::Storage;
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+
+const REPUTATION_PREFIX: &str = "reputation:";
+/// Observations needed before a peer can be excluded for a low score
+const MIN_OBSERVATIONS: f64 = 20.0;
+/// Time after which an observation counts half as much, so that old behaviour fades out and
+/// a peer that recovered is trusted again
+const HALF_LIFE_SECS: f64 = 86_400.0;
+/// Weight of each new latency sample in the moving average
+const LATENCY_SMOOTHING: f64 = 0.2;
+/// Latency at which the latency component of the score drops to one half
+const REFERENCE_LATENCY_MS: f64 = 1000.0;
+
+/// What this node has observed of a peer's behaviour. Counts decay with `HALF_LIFE_SECS` since
+/// `updated_at`, so they weigh recent observations more.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PeerReputation {
+    pub node_id: String,
+    pub probes_ok: f64,
+    pub probes_failed: f64,
+    /// Exponential moving average of the health check latency
+    pub avg_latency_ms: Option<f64>,
+    /// Prevotes for the value the round went on to commit
+    pub votes_agreed: f64,
+    /// Prevotes for nil or another value in a round that committed
+    pub votes_disagreed: f64,
+    /// Committed rounds the peer sent no prevote for
+    pub votes_missed: f64,
+    pub updated_at: u64,
+}
+
+impl PeerReputation {
+    fn new(node_id: &str) -> Self {
+        PeerReputation {
+            node_id: node_id.to_string(),
+            probes_ok: 0.0,
+            probes_failed: 0.0,
+            avg_latency_ms: None,
+            votes_agreed: 0.0,
+            votes_disagreed: 0.0,
+            votes_missed: 0.0,
+            updated_at: unix_timestamp(),
+        }
+    }
+
+    /// The record as of `now`, with its counts decayed since `updated_at`
+    fn decayed(&self, now: u64) -> Self {
+        let elapsed = now.saturating_sub(self.updated_at) as f64;
+        let factor = (0.5f64).powf(elapsed / HALF_LIFE_SECS);
+        PeerReputation {
+            node_id: self.node_id.clone(),
+            probes_ok: self.probes_ok * factor,
+            probes_failed: self.probes_failed * factor,
+            avg_latency_ms: self.avg_latency_ms,
+            votes_agreed: self.votes_agreed * factor,
+            votes_disagreed: self.votes_disagreed * factor,
+            votes_missed: self.votes_missed * factor,
+            updated_at: now.max(self.updated_at),
+        }
+    }
+
+    pub fn observations(&self) -> f64 {
+        self.probes_ok + self.probes_failed + self.votes_agreed + self.votes_disagreed + self.votes_missed
+    }
+
+    /// Share of health checks and expected votes the peer answered
+    pub fn response_rate(&self) -> f64 {
+        let answered = self.probes_ok + self.votes_agreed + self.votes_disagreed;
+        ratio(answered, self.observations())
+    }
+
+    /// Share of the peer's votes that matched the committed outcome
+    pub fn agreement_rate(&self) -> f64 {
+        ratio(self.votes_agreed, self.votes_agreed + self.votes_disagreed)
+    }
+
+    /// Score between 0 and 1 combining response rate, vote agreement and latency
+    pub fn score(&self) -> f64 {
+        let latency = self.avg_latency_ms.map_or(1.0, |latency| {
+            REFERENCE_LATENCY_MS / (REFERENCE_LATENCY_MS + latency)
+        });
+        0.4 * self.response_rate() + 0.4 * self.agreement_rate() + 0.2 * latency
+    }
+}
+
+/// Ratio treating a peer without observations as fully reliable
+fn ratio(part: f64, total: f64) -> f64 {
+    if total <= 0.0 { 1.0 } else { part / total }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReputationReport {
+    #[serde(flatten)]
+    pub reputation: PeerReputation,
+    pub response_rate: f64,
+    pub agreement_rate: f64,
+    pub score: f64,
+    pub excluded: bool,
+}
+
+/// Per-peer reputation, persisted in `Storage` so it survives restarts
+pub struct ReputationTracker {
+    storage: Storage,
+    peers: HashMap<String, PeerReputation>,
+    min_score: f64,
+}
+
+impl ReputationTracker {
+    pub fn load(storage: Storage, min_score: f64) -> Self {
+        let peers = storage
+            .scan_prefix(REPUTATION_PREFIX)
+            .into_iter()
+            .filter_map(|(_, value)| serde_json::from_str::<PeerReputation>(&value).ok())
+            .map(|reputation| (reputation.node_id.clone(), reputation))
+            .collect();
+
+        ReputationTracker { storage, peers, min_score }
+    }
+
+    fn update(&mut self, node_id: &str, apply: impl FnOnce(&mut PeerReputation)) {
+        let now = unix_timestamp();
+        let reputation = self.peers
+            .entry(node_id.to_string())
+            .or_insert_with(|| PeerReputation::new(node_id));
+        *reputation = reputation.decayed(now);
+        apply(reputation);
+        self.storage.store_data(
+            &format!("{}{}", REPUTATION_PREFIX, node_id),
+            &serde_json::to_string(reputation).unwrap()
+        );
+    }
+
+    pub fn record_probe_success(&mut self, node_id: &str, latency_ms: u64) {
+        self.update(node_id, |reputation| {
+            reputation.probes_ok += 1.0;
+            let latency = latency_ms as f64;
+            reputation.avg_latency_ms = Some(
+                reputation.avg_latency_ms.map_or(latency, |avg| {
+                    avg + LATENCY_SMOOTHING * (latency - avg)
+                })
+            );
+        });
+    }
+
+    pub fn record_probe_failure(&mut self, node_id: &str) {
+        self.update(node_id, |reputation| {
+            reputation.probes_failed += 1.0;
+        });
+    }
+
+    /// Compare the prevotes of a committed round with the committed value
+    pub fn record_votes(
+        &mut self,
+        validators: &[String],
+        self_id: &str,
+        prevotes: &HashMap<String, Option<String>>,
+        committed: &str
+    ) {
+        for validator in validators.iter().filter(|id| id.as_str() != self_id) {
+            self.update(validator, |reputation| {
+                match prevotes.get(validator) {
+                    Some(Some(value)) if value == committed => {
+                        reputation.votes_agreed += 1.0;
+                    }
+                    Some(_) => {
+                        reputation.votes_disagreed += 1.0;
+                    }
+                    None => {
+                        reputation.votes_missed += 1.0;
+                    }
+                }
+            });
+        }
+    }
+
+    /// A peer's record with its counts decayed to the present
+    fn current(&self, node_id: &str) -> Option<PeerReputation> {
+        self.peers.get(node_id).map(|reputation| reputation.decayed(unix_timestamp()))
+    }
+
+    pub fn score(&self, node_id: &str) -> f64 {
+        self.current(node_id).map_or(1.0, |reputation| reputation.score())
+    }
+
+    /// Whether a peer has been observed enough recently and scores below the configured minimum
+    pub fn is_excluded(&self, node_id: &str) -> bool {
+        self.current(node_id).map_or(false, |reputation| {
+            reputation.observations() >= MIN_OBSERVATIONS && reputation.score() < self.min_score
+        })
+    }
+
+    pub fn report(&self, node_id: &str) -> Option<ReputationReport> {
+        self.current(node_id).map(|reputation| ReputationReport {
+            reputation: reputation.clone(),
+            response_rate: reputation.response_rate(),
+            agreement_rate: reputation.agreement_rate(),
+            score: reputation.score(),
+            excluded: self.is_excluded(node_id),
+        })
+    }
+
+    pub fn all(&self) -> Vec<ReputationReport> {
+        self.peers
+            .keys()
+            .filter_map(|node_id| self.report(node_id))
+            .collect()
+    }
+}
@@ -0,0 +1,148 @@
+use crate::ledger::LedgerClient;
+use crate::node::node::unix_timestamp;
+use crate::storage::Storage;
+use anyhow::{ anyhow, Result };
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+
+const BOND_PREFIX: &str = "bond:";
+/// Interval between two checks of the known validators' bonds against the ledger
+const STAKE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Stake withdrawn from a bond, still liable for misbehaviour until `completes_at`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Unbonding {
+    pub amount: u64,
+    pub started_at: u64,
+    pub completes_at: u64,
+}
+
+/// A validator's bond as last verified against the ledger
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Bond {
+    pub node_id: String,
+    pub wallet_address: String,
+    pub bonded: u64,
+    pub unbonding: Vec<Unbonding>,
+    pub verified_at: u64,
+}
+
+/// Bonded stake of the known validators, persisted in `Storage` so unbonding periods survive restarts
+pub struct StakeRegistry {
+    storage: Storage,
+    bonds: HashMap<String, Bond>,
+    min_stake: u64,
+    unbonding_period_secs: u64,
+}
+
+impl StakeRegistry {
+    pub fn load(storage: Storage, min_stake: u64, unbonding_period_secs: u64) -> Self {
+        let bonds = storage
+            .scan_prefix(BOND_PREFIX)
+            .into_iter()
+            .filter_map(|(_, value)| serde_json::from_str::<Bond>(&value).ok())
+            .map(|bond| (bond.node_id.clone(), bond))
+            .collect();
+
+        StakeRegistry { storage, bonds, min_stake, unbonding_period_secs }
+    }
+
+    pub fn min_stake(&self) -> u64 {
+        self.min_stake
+    }
+
+    /// The node other than `node_id` that `wallet_address` backs, if any
+    pub fn backed_node(&self, wallet_address: &str, node_id: &str) -> Option<&str> {
+        self.bonds
+            .values()
+            .find(|bond| bond.wallet_address == wallet_address && bond.node_id != node_id)
+            .map(|bond| bond.node_id.as_str())
+    }
+
+    /// Record the amount the ledger reports bonded by a node's wallet; any decrease since the
+    /// last check starts an unbonding period. A wallet can only back one node, so that the same
+    /// stake never weighs twice.
+    pub fn record_bond(&mut self, node_id: &str, wallet_address: &str, bonded: u64) -> Result<()> {
+        if let Some(other) = self.backed_node(wallet_address, node_id) {
+            return Err(anyhow!("Wallet {} already backs node {}", wallet_address, other));
+        }
+        let now = unix_timestamp();
+        let bond = self.bonds.entry(node_id.to_string()).or_insert_with(|| Bond {
+            node_id: node_id.to_string(),
+            wallet_address: wallet_address.to_string(),
+            bonded,
+            unbonding: Vec::new(),
+            verified_at: now,
+        });
+
+        // Switching wallets unbonds the whole stake of the previous one
+        let unbonded = if bond.wallet_address == wallet_address {
+            bond.bonded.saturating_sub(bonded)
+        } else {
+            bond.bonded
+        };
+        if unbonded > 0 {
+            bond.unbonding.push(Unbonding {
+                amount: unbonded,
+                started_at: now,
+                completes_at: now + self.unbonding_period_secs,
+            });
+            println!("Node {} started unbonding {} stake", node_id, unbonded);
+        }
+        bond.wallet_address = wallet_address.to_string();
+        bond.bonded = bonded;
+        bond.verified_at = now;
+        bond.unbonding.retain(|unbonding| unbonding.completes_at > now);
+
+        self.storage.store_data(
+            &format!("{}{}", BOND_PREFIX, node_id),
+            &serde_json::to_string(bond).unwrap()
+        );
+        Ok(())
+    }
+
+    /// Voting weight of a node: its bonded stake if it meets the minimum, 0 otherwise
+    pub fn stake(&self, node_id: &str) -> u64 {
+        self.bonds
+            .get(node_id)
+            .map(|bond| bond.bonded)
+            .filter(|bonded| *bonded >= self.min_stake)
+            .unwrap_or(0)
+    }
+
+    pub fn is_bonded(&self, node_id: &str) -> bool {
+        self.stake(node_id) > 0
+    }
+
+    pub fn get(&self, node_id: &str) -> Option<&Bond> {
+        self.bonds.get(node_id)
+    }
+
+    pub fn all(&self) -> Vec<Bond> {
+        self.bonds.values().cloned().collect()
+    }
+}
+
+/// Re-check every recorded bond periodically, so that withdrawn stake is noticed and its
+/// unbonding period tracked. Only wallets a node proved it owns when registering are checked;
+/// wallet addresses learnt from discovery never bind stake to a node.
+pub async fn run_stake_checks(registry: Arc<Mutex<StakeRegistry>>, ledger: LedgerClient) {
+    loop {
+        let bonds = registry.lock().await.all();
+
+        for bond in bonds {
+            let result = match ledger.bonded_stake(&bond.wallet_address).await {
+                Ok(bonded) => registry.lock().await.record_bond(&bond.node_id, &bond.wallet_address, bonded),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to check the stake of node {}: {}", bond.node_id, e);
+            }
+        }
+
+        tokio::time::sleep(STAKE_CHECK_INTERVAL).await;
+    }
+}
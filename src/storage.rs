@@ -16,4 +16,28 @@ impl Storage {
     pub fn store_data(&self, key: &str, value: &str) {
         self.db.put(key, value).unwrap();
     }
+
+    pub fn get_data(&self, key: &str) -> Option<String> {
+        self.db
+            .get(key)
+            .unwrap()
+            .map(|value| String::from_utf8_lossy(&value).into_owned())
+    }
+
+    pub fn delete_data(&self, key: &str) {
+        self.db.delete(key).unwrap();
+    }
+
+    /// Return every key/value pair whose key starts with `prefix`, in key order
+    pub fn scan_prefix(&self, prefix: &str) -> Vec<(String, String)> {
+        self.db
+            .prefix_iterator(prefix)
+            .map(|item| item.unwrap())
+            .take_while(|(key, _)| key.starts_with(prefix.as_bytes()))
+            .map(|(key, value)| (
+                String::from_utf8_lossy(&key).into_owned(),
+                String::from_utf8_lossy(&value).into_owned(),
+            ))
+            .collect()
+    }
 }
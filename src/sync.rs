@@ -0,0 +1,300 @@
+use crate::batch::{ Batch, BatchLog };
+use crate::consensus::quorum_weight;
+use crate::epoch::ValidatorSet;
+use crate::staking::StakeRegistry;
+use crate::health::HealthState;
+use crate::node::node::{ Node, NodeList };
+use crate::validation::{ redact_transaction, transaction_hash };
+use anyhow::{ anyhow, Result };
+use reqwest::Client;
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::{ sleep, Duration };
+use tracing::info;
+
+/// Maximum number of batches served per `/batches` request
+pub const SYNC_PAGE_SIZE: u64 = 100;
+const SYNC_TIMEOUT: Duration = Duration::from_secs(10);
+/// Delay before the startup sync is retried after it failed
+const SYNC_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A batch together with the redacted bodies of its transactions, as served to syncing peers
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SyncBatch {
+    pub batch: Batch,
+    pub transactions: Vec<(String, Value)>,
+}
+
+/// Collect the batches `from..from + limit` and their transactions from the local log, without
+/// the transactions' private keys
+pub fn export_range(batch_log: &BatchLog, from: u64, limit: u64) -> Vec<SyncBatch> {
+    let limit = limit.clamp(1, SYNC_PAGE_SIZE);
+    batch_log
+        .range(from, from.saturating_add(limit - 1))
+        .into_iter()
+        .map(|batch| {
+            let transactions = batch.tx_hashes
+                .iter()
+                .filter_map(|hash| {
+                    batch_log.get_transaction(hash).map(|data| (hash.clone(), redact_transaction(&data)))
+                })
+                .collect();
+            SyncBatch { batch, transactions }
+        })
+        .collect()
+}
+
+/// Agree on the first validator set and catch up with the peers holding longer logs, retrying
+/// until that succeeds, then mark the node ready to vote
+pub async fn run_initial_sync(
+    batch_log: Arc<Mutex<BatchLog>>,
+    node_list: Arc<Mutex<NodeList>>,
+    health: Arc<HealthState>,
+    stakes: Arc<Mutex<StakeRegistry>>,
+    self_id: String
+) {
+    let client = Client::new();
+
+    // A node that votes or starts a new log before catching up would fork from its peers
+    loop {
+        let synced = match ensure_genesis(&batch_log, &node_list, &stakes, &client, &self_id).await {
+            Ok(()) => catch_up(&batch_log, &node_list, &client, &self_id).await,
+            Err(e) => Err(e),
+        };
+        match synced {
+            Ok(height) => {
+                info!("Batch log synced up to height {}", height);
+                break;
+            }
+            Err(e) => {
+                tracing::error!("Batch log sync failed, retrying in {:?}: {}", SYNC_RETRY_INTERVAL, e);
+                sleep(SYNC_RETRY_INTERVAL).await;
+            }
+        }
+    }
+
+    health.set_ready(true);
+}
+
+/// Set the first epoch's validators, which every later batch and certificate is checked against,
+/// unless `genesis_validators` already did. A set is only taken from the peers once more than two
+/// thirds of the known peers serve the same one, so no single peer picks it. A node without
+/// peers builds it from itself and the known nodes: the known bonded nodes, or every known node
+/// with equal weight on a network nobody has staked on yet.
+async fn ensure_genesis(
+    batch_log: &Arc<Mutex<BatchLog>>,
+    node_list: &Arc<Mutex<NodeList>>,
+    stakes: &Arc<Mutex<StakeRegistry>>,
+    client: &Client,
+    self_id: &str
+) -> Result<()> {
+    if batch_log.lock().await.has_genesis() {
+        return Ok(());
+    }
+    let nodes = {
+        let node_list_guard = node_list.lock().await;
+        node_list_guard.get_nodes()
+    };
+    let peers: Vec<&Node> = nodes
+        .iter()
+        .filter(|node| node.id != self_id)
+        .collect();
+
+    let genesis = if peers.is_empty() {
+        let stakes = stakes.lock().await;
+        let bonded: Vec<Node> = nodes
+            .iter()
+            .filter(|node| stakes.is_bonded(&node.id))
+            .cloned()
+            .collect();
+        if bonded.is_empty() {
+            ValidatorSet::from_nodes(0, 1, &nodes, |_| 0)
+        } else {
+            ValidatorSet::from_nodes(0, 1, &bonded, |id| stakes.stake(id))
+        }
+    } else {
+        agreed_genesis(&peers, client).await?
+    };
+    batch_log.lock().await.init_genesis(genesis);
+    Ok(())
+}
+
+/// The first validator set served identically by a quorum of `peers`
+async fn agreed_genesis(peers: &[&Node], client: &Client) -> Result<ValidatorSet> {
+    let mut served: HashMap<String, (ValidatorSet, u64)> = HashMap::new();
+    for peer in peers {
+        match fetch_genesis(peer, client).await {
+            Ok(validators) => {
+                let key = serde_json::to_string(&validators)?;
+                served.entry(key).or_insert((validators, 0)).1 += 1;
+            }
+            Err(e) => eprintln!("Failed to fetch the first validator set from node {}: {}", peer.id, e),
+        }
+    }
+
+    let needed = quorum_weight(peers.len() as u64);
+    served
+        .into_values()
+        .find(|(_, count)| *count >= needed)
+        .map(|(validators, _)| validators)
+        .ok_or_else(|| {
+            anyhow!(
+                "Fewer than {} of the {} known peers serve the same first validator set",
+                needed,
+                peers.len()
+            )
+        })
+}
+
+/// Sync the log from the peers with longer ones, returning the new height. Fails if no peer
+/// answered, or if peers were ahead and the log reached none of their heights.
+pub async fn catch_up(
+    batch_log: &Arc<Mutex<BatchLog>>,
+    node_list: &Arc<Mutex<NodeList>>,
+    client: &Client,
+    self_id: &str
+) -> Result<u64> {
+    let peers: Vec<Node> = {
+        let node_list_guard = node_list.lock().await;
+        node_list_guard
+            .get_nodes()
+            .into_iter()
+            .filter(|node| node.id != self_id)
+            .collect()
+    };
+
+    // Ask every peer for its height and try the longest logs first
+    let peer_count = peers.len();
+    let mut heights = Vec::new();
+    for peer in peers {
+        match fetch_height(&peer, client).await {
+            Ok(height) => heights.push((height, peer)),
+            Err(e) => eprintln!("Failed to fetch batch height from node {}: {}", peer.id, e),
+        }
+    }
+    if peer_count > 0 && heights.is_empty() {
+        return Err(anyhow!("None of the {} known peers answered", peer_count));
+    }
+    heights.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let start_height = batch_log.lock().await.height();
+    let ahead: Vec<u64> = heights
+        .iter()
+        .map(|(height, _)| *height)
+        .filter(|height| *height > start_height)
+        .collect();
+
+    for (peer_height, peer) in heights {
+        let local_height = batch_log.lock().await.height();
+        if peer_height <= local_height {
+            break;
+        }
+
+        // Peers rank themselves by the height they claim, but every batch they serve is checked
+        // against the validator sets descending from the first one, which no single peer picks
+        if !batch_log.lock().await.has_genesis() {
+            return Err(anyhow!("The first validator set is not known yet"));
+        }
+
+        println!("Syncing batches {}..={} from node {}", local_height + 1, peer_height, peer.id);
+        if let Err(e) = sync_from_peer(&peer, peer_height, batch_log, client).await {
+            eprintln!("Failed to sync from node {}: {}", peer.id, e);
+        }
+    }
+
+    let height = batch_log.lock().await.height();
+    if !ahead.is_empty() && ahead.iter().all(|peer_height| *peer_height > height) {
+        return Err(anyhow!("Synced up to height {} while peers are at up to {}", height, ahead[0]));
+    }
+    Ok(height)
+}
+
+async fn fetch_height(peer: &Node, client: &Client) -> Result<u64> {
+    let response = client.get(peer.url("/batches/latest")).timeout(SYNC_TIMEOUT).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(0);
+    }
+    if !response.status().is_success() {
+        return Err(anyhow!("Status: {}", response.status()));
+    }
+
+    let batch: Batch = response.json().await?;
+    Ok(batch.header.height)
+}
+
+/// The validator set of epoch 0, which every later set descends from
+async fn fetch_genesis(peer: &Node, client: &Client) -> Result<ValidatorSet> {
+    let response = client.get(peer.url("/epochs/0")).timeout(SYNC_TIMEOUT).send().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Status: {}", response.status()));
+    }
+
+    let validators: ValidatorSet = response.json().await?;
+    if validators.epoch != 0 || validators.start_height != 1 || validators.validators.is_empty() {
+        return Err(anyhow!("Node returned an invalid first validator set"));
+    }
+    Ok(validators)
+}
+
+async fn sync_from_peer(
+    peer: &Node,
+    peer_height: u64,
+    batch_log: &Arc<Mutex<BatchLog>>,
+    client: &Client
+) -> Result<()> {
+    loop {
+        let from = batch_log.lock().await.height() + 1;
+        if from > peer_height {
+            return Ok(());
+        }
+
+        let url = format!("{}?from={}&limit={}", peer.url("/batches"), from, SYNC_PAGE_SIZE);
+        let response = client.get(&url).timeout(SYNC_TIMEOUT).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Status: {}", response.status()));
+        }
+
+        let page: Vec<SyncBatch> = response.json().await?;
+        if page.is_empty() {
+            return Err(anyhow!("Node returned no batches from height {}", from));
+        }
+
+        for sync_batch in page {
+            let mut batch_log = batch_log.lock().await;
+            verify_sync_batch(&sync_batch, &batch_log)?;
+            batch_log.append(sync_batch.batch, sync_batch.transactions)?;
+        }
+    }
+}
+
+/// Check the batch's signatures against its height's validator set and that the transactions
+/// match its hashes; the hash link, Merkle root and epoch are checked by `BatchLog::append`
+fn verify_sync_batch(sync_batch: &SyncBatch, batch_log: &BatchLog) -> Result<()> {
+    let batch = &sync_batch.batch;
+
+    let validators = batch_log
+        .validator_set(batch.header.height)
+        .ok_or_else(|| anyhow!("No validator set for batch {}", batch.header.height))?;
+    if !batch.header.verify_signatures(validators) {
+        return Err(anyhow!("Batch {} has invalid signatures", batch.header.height));
+    }
+
+    let hashes: Vec<&String> = sync_batch.transactions
+        .iter()
+        .map(|(hash, _)| hash)
+        .collect();
+    if hashes != batch.tx_hashes.iter().collect::<Vec<_>>() {
+        return Err(anyhow!("Batch {} is missing transactions", batch.header.height));
+    }
+
+    for (hash, data) in &sync_batch.transactions {
+        if &transaction_hash(data) != hash {
+            return Err(anyhow!("Transaction {} does not match its hash", hash));
+        }
+    }
+
+    Ok(())
+}
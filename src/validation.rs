@@ -1,16 +1,20 @@
-use crate::node::node::Node;
+use crate::address::{ Address, AddressFormat };
+use crate::decimal::Decimal;
 use serde::{ Deserialize, Serialize };
 use serde_json::{ Value, from_value };
+use sha2::{ Digest, Sha256 };
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TransactionData {
     pub transaction_type: String,
-    pub sender: String,
+    pub sender: Address,
     pub private_key: String,
-    pub receiver: String,
-    pub amount: u64,
+    pub receiver: Address,
+    pub amount: Decimal,
     pub denom: String,
-    pub fee: u64,
+    pub fee: Decimal,
+    /// Position of the transaction among its sender's, starting at 0
+    pub nonce: u64,
     pub flags: u64,
     pub data_type: String,
     pub data: DataField,
@@ -33,7 +37,35 @@ pub struct Meta {
     pub value: String,
 }
 
-pub async fn validate_data(_node: &Node, data: &Value) -> bool {
+/// Field standing in for `private_key` in transactions served to other nodes
+const PRIVATE_KEY_HASH: &str = "private_key_hash";
+
+/// The transaction with its `private_key` replaced by the key's SHA-256, as served to syncing
+/// peers: it can no longer be submitted to the ledger, but still has the same hash
+pub fn redact_transaction(data: &Value) -> Value {
+    let mut redacted = data.clone();
+    if let Some(object) = redacted.as_object_mut() {
+        if let Some(private_key) = object.remove("private_key") {
+            let digest = hex::encode(Sha256::digest(private_key.to_string().as_bytes()));
+            object.insert(PRIVATE_KEY_HASH.to_string(), Value::String(digest));
+        }
+    }
+    redacted
+}
+
+/// Whether a transaction still carries its `private_key`
+pub fn has_private_key(data: &Value) -> bool {
+    data.get("private_key").is_some()
+}
+
+/// Hex SHA-256 of the JSON encoding of the redacted transaction, used as its identifier across
+/// the network; a transaction and its redacted copy share it
+pub fn transaction_hash(data: &Value) -> String {
+    hex::encode(Sha256::digest(redact_transaction(data).to_string().as_bytes()))
+}
+
+/// Check the fields of a transaction, including that both addresses carry the network prefix
+pub async fn validate_data(data: &Value, address_format: &AddressFormat) -> bool {
     println!("Validating data: {:#?}", data);
 
     match from_value::<TransactionData>(data.clone()) {
@@ -43,13 +75,11 @@ pub async fn validate_data(_node: &Node, data: &Value) -> bool {
                 return false;
             }
 
-            if transaction.sender.is_empty() {
-                println!("Validation failed: Sender address is empty");
-                return false;
-            }
-
-            if transaction.receiver.is_empty() {
-                println!("Validation failed: Receiver address is empty");
+            let addresses = address_format
+                .check(&transaction.sender)
+                .and_then(|_| address_format.check(&transaction.receiver));
+            if let Err(e) = addresses {
+                println!("Validation failed: {}", e);
                 return false;
             }
 
@@ -58,7 +88,7 @@ pub async fn validate_data(_node: &Node, data: &Value) -> bool {
                 return false;
             }
 
-            if transaction.amount == 0 {
+            if transaction.amount.is_zero() {
                 println!("Validation failed: Transaction amount is zero");
                 return false;
             }
@@ -68,7 +98,7 @@ pub async fn validate_data(_node: &Node, data: &Value) -> bool {
                 return false;
             }
 
-            if transaction.fee == 0 {
+            if transaction.fee.is_zero() {
                 println!("Validation failed: Transaction fee is zero");
                 return false;
             }